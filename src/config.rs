@@ -2,12 +2,33 @@
 //!
 //! Configures the application based on the passed command line arguments
 
-use crate::db::save_path::SavePath;
+use crate::db::category::{check_category_name, CategoryChange, CategoryOperation};
+use crate::db::counters::{parse_duration, parse_size, AdjustMode, StatsAdjustment};
+use crate::db::diff::DiffRun;
+use crate::db::export::{ExportFormat, ExportRun};
+use crate::db::filter::TorrentFilter;
+use crate::db::import::{ImportRun, ImportSource};
+use crate::db::limits::{
+    parse_count_limit, parse_rate_limit, parse_ratio_limit, parse_time_limit, TorrentLimits,
+};
+use crate::db::merge::{ConflictMode, MergeRun};
+use crate::db::migrate::{Migration, PathMapping};
+use crate::db::passkey::PasskeyChange;
+use crate::db::queue::{QueueOperation, SortKey};
+use crate::db::rename::{FileRename, RenameRequest};
+use crate::db::rules::{load_rules, RuleSet};
+use crate::db::save_path::{RepairSource, SavePath, SavePathRepair};
+use crate::db::state::{parse_stop_condition, ManagementMode, RunState, StateChange};
+use crate::db::tag::{parse_tag_args, TagOperation};
+use crate::db::tracker_policy::{load_policy, PolicyRun};
 use crate::db::tracker_url::TrackerUrl;
+use crate::db::trackers::{TrackerEdit, TrackerOperation};
+use crate::db::webseeds::{WebSeedEdit, WebSeedOperation};
+use crate::path::{fragment_to_portable, QbPath, Separator};
 use argh::FromArgs;
 use core::panic;
 use directories::BaseDirs;
-use std::path::{Path, PathBuf, MAIN_SEPARATOR};
+use std::path::{Path, PathBuf};
 
 /// Returns the OS-specific qB .torrent data directory
 fn get_qb_dir() -> PathBuf {
@@ -15,6 +36,30 @@ fn get_qb_dir() -> PathBuf {
     base_dirs.data_local_dir().join("qBittorrent")
 }
 
+/// Returns the separator to use in updated paths, defaulting to the current OS style
+fn get_separator(use_unix_sep: bool, use_win_sep: bool) -> Separator {
+    if use_unix_sep {
+        Separator::Unix
+    } else if use_win_sep {
+        Separator::Windows
+    } else {
+        Separator::os_default()
+    }
+}
+
+/// Parses `--map` arguments in the form `old=new` into path mappings
+fn parse_mappings(maps: &[String]) -> Result<Vec<PathMapping>, String> {
+    maps.iter()
+        .map(|map| match map.split_once('=') {
+            Some((from, to)) => Ok(PathMapping {
+                from: QbPath::parse(from),
+                to: QbPath::parse(to),
+            }),
+            None => Err(format!("path mapping {map} is not in the form 'old=new'")),
+        })
+        .collect()
+}
+
 /// CLI argument options
 #[derive(Debug, FromArgs)]
 struct CLIOpts {
@@ -39,12 +84,763 @@ struct CLIOpts {
     /// force using Windows backslash '\' separators
     #[argh(switch)]
     use_win_sep: bool,
+    /// also replace the path string in download paths
+    #[argh(switch)]
+    include_download_path: bool,
+    /// download path string to replace
+    #[argh(option)]
+    old_download_path: Option<String>,
+    /// new download path string
+    #[argh(option)]
+    new_download_path: Option<String>,
     /// tracker string to replace
     #[argh(option)]
     old_tracker: Option<String>,
     /// new tracker string
     #[argh(option)]
     new_tracker: Option<String>,
+    /// only include torrents whose hash starts with this value, can be repeated
+    #[argh(option)]
+    only_hash: Vec<String>,
+    /// only include torrents in this category, '' for uncategorized
+    #[argh(option)]
+    only_category: Option<String>,
+    /// only include torrents with this tag
+    #[argh(option)]
+    only_tag: Option<String>,
+    /// only include torrents with a tracker URL containing this string
+    #[argh(option)]
+    only_tracker: Option<String>,
+    /// only include torrents with a tracker on this domain or its subdomains
+    #[argh(option)]
+    only_tracker_host: Option<String>,
+    /// only include private torrents
+    #[argh(switch)]
+    only_private: bool,
+    /// only include public torrents
+    #[argh(switch)]
+    only_public: bool,
+    /// only include torrents saved in or below this path
+    #[argh(option)]
+    only_path: Option<String>,
+    /// only include torrents whose name contains this string
+    #[argh(option)]
+    only_name: Option<String>,
+    #[argh(subcommand)]
+    command: Option<CLICommand>,
+}
+
+/// CLI subcommands
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum CLICommand {
+    Doctor(DoctorOpts),
+    Repair(RepairOpts),
+    Migrate(MigrateOpts),
+    Category(CategoryOpts),
+    Tag(TagOpts),
+    Rules(RulesOpts),
+    Limits(LimitsOpts),
+    Queue(QueueOpts),
+    State(StateOpts),
+    Rename(RenameOpts),
+    Trackers(TrackersOpts),
+    Passkey(PasskeyOpts),
+    Webseeds(WebseedsOpts),
+    Stats(StatsOpts),
+    Import(ImportOpts),
+    Export(ExportOpts),
+    Merge(MergeOpts),
+    Diff(DiffOpts),
+}
+
+/// summarise transfer statistics of the selected torrents, or set or adjust them
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "stats")]
+struct StatsOpts {
+    #[argh(subcommand)]
+    operation: Option<StatsOperationOpts>,
+}
+
+/// Transfer statistics operations
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum StatsOperationOpts {
+    Set(StatsSetOpts),
+    Add(StatsAddOpts),
+}
+
+/// set transfer statistics of the selected torrents, e.g. to reset them to 0
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "set")]
+struct StatsSetOpts {
+    /// total uploaded, e.g. 0, 500M or 1.5G
+    #[argh(option)]
+    uploaded: Option<String>,
+    /// total downloaded, e.g. 0, 500M or 1.5G
+    #[argh(option)]
+    downloaded: Option<String>,
+    /// seeding time, in seconds or with an s, m, h, d or w suffix, e.g. 30d
+    #[argh(option)]
+    seeding_time: Option<String>,
+    /// active time, in seconds or with an s, m, h, d or w suffix, e.g. 30d
+    #[argh(option)]
+    active_time: Option<String>,
+}
+
+/// add to transfer statistics of the selected torrents, negative values subtract
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "add")]
+struct StatsAddOpts {
+    /// amount to add to total uploaded, e.g. 500M or -1.5G
+    #[argh(option)]
+    uploaded: Option<String>,
+    /// amount to add to total downloaded, e.g. 500M or -1.5G
+    #[argh(option)]
+    downloaded: Option<String>,
+    /// time to add to seeding time, in seconds or with an s, m, h, d or w suffix, e.g. 30d
+    #[argh(option)]
+    seeding_time: Option<String>,
+    /// time to add to active time, in seconds or with an s, m, h, d or w suffix, e.g. 30d
+    #[argh(option)]
+    active_time: Option<String>,
+}
+
+/// check torrents.db for problems without modifying it
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "doctor")]
+struct DoctorOpts {}
+
+/// reconcile target_save_path with the fastresume save path
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "repair")]
+struct RepairOpts {
+    /// save path location to treat as correct: 'target' or 'fastresume'
+    #[argh(option)]
+    from: String,
+    /// force using path slash '/' separators
+    #[argh(switch)]
+    use_unix_sep: bool,
+    /// force using Windows backslash '\' separators
+    #[argh(switch)]
+    use_win_sep: bool,
+}
+
+/// move save and download paths to new drives or mount points, e.g. from Windows to Linux
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "migrate")]
+struct MigrateOpts {
+    /// path mapping as 'old=new', e.g. 'D:\=/mnt/d', can be repeated; lists path roots if omitted
+    #[argh(option)]
+    map: Vec<String>,
+    /// force using path slash '/' separators
+    #[argh(switch)]
+    use_unix_sep: bool,
+    /// force using Windows backslash '\' separators
+    #[argh(switch)]
+    use_win_sep: bool,
+}
+
+/// set, clear or rename categories of the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "category")]
+struct CategoryOpts {
+    /// also update categories.json in the qB config directory
+    #[argh(switch)]
+    update_categories: bool,
+    #[argh(subcommand)]
+    operation: CategoryOperationOpts,
+}
+
+/// Category operations
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum CategoryOperationOpts {
+    Set(CategorySetOpts),
+    Clear(CategoryClearOpts),
+    Rename(CategoryRenameOpts),
+}
+
+/// set the category of the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "set")]
+struct CategorySetOpts {
+    /// category name
+    #[argh(positional)]
+    category: String,
+}
+
+/// remove the category from the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "clear")]
+struct CategoryClearOpts {}
+
+/// rename a category on the selected torrents, or on all torrents if none are selected
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "rename")]
+struct CategoryRenameOpts {
+    /// existing category name
+    #[argh(positional)]
+    old: String,
+    /// new category name
+    #[argh(positional)]
+    new: String,
+}
+
+/// add, remove, rename or replace tags of the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "tag")]
+struct TagOpts {
+    #[argh(subcommand)]
+    operation: TagOperationOpts,
+}
+
+/// Tag operations
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum TagOperationOpts {
+    Add(TagAddOpts),
+    Remove(TagRemoveOpts),
+    Rename(TagRenameOpts),
+    Set(TagSetOpts),
+}
+
+/// add tags to the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "add")]
+struct TagAddOpts {
+    /// tags to add, separated by spaces or commas
+    #[argh(positional)]
+    tags: Vec<String>,
+}
+
+/// remove tags from the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "remove")]
+struct TagRemoveOpts {
+    /// tags to remove, separated by spaces or commas
+    #[argh(positional)]
+    tags: Vec<String>,
+}
+
+/// rename a tag on the selected torrents, or on all torrents if none are selected
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "rename")]
+struct TagRenameOpts {
+    /// existing tag name
+    #[argh(positional)]
+    old: String,
+    /// new tag name
+    #[argh(positional)]
+    new: String,
+}
+
+/// replace the tags of the selected torrents, no tags clears them
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "set")]
+struct TagSetOpts {
+    /// new tags, separated by spaces or commas
+    #[argh(positional)]
+    tags: Vec<String>,
+}
+
+/// assign categories and tags to the selected torrents using rules from a JSON file
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "rules")]
+struct RulesOpts {
+    /// path to the rules file
+    #[argh(positional)]
+    rules_file: PathBuf,
+    /// report what would change without modifying the database
+    #[argh(switch)]
+    dry_run: bool,
+}
+
+/// set seeding, rate and connection limits of the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "limits")]
+struct LimitsOpts {
+    /// ratio limit, e.g. '2.0', 'global' or 'unlimited'
+    #[argh(option)]
+    ratio: Option<String>,
+    /// seeding time limit, e.g. '90' minutes, '12h', '30d', 'global' or 'unlimited'
+    #[argh(option)]
+    seeding_time: Option<String>,
+    /// inactive seeding time limit, e.g. '90' minutes, '12h', 'global' or 'unlimited'
+    #[argh(option)]
+    inactive_seeding_time: Option<String>,
+    /// upload rate limit, e.g. '500K', '2M' or 'unlimited'
+    #[argh(option)]
+    upload_limit: Option<String>,
+    /// download rate limit, e.g. '500K', '2M' or 'unlimited'
+    #[argh(option)]
+    download_limit: Option<String>,
+    /// maximum number of connections or 'unlimited'
+    #[argh(option)]
+    max_connections: Option<String>,
+    /// maximum number of upload slots or 'unlimited'
+    #[argh(option)]
+    max_uploads: Option<String>,
+}
+
+/// reorder the download queue, seeding torrents are left alone
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "queue")]
+struct QueueOpts {
+    #[argh(subcommand)]
+    operation: QueueOperationOpts,
+}
+
+/// Queue operations
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum QueueOperationOpts {
+    Sort(QueueSortOpts),
+    Top(QueueTopOpts),
+    Bottom(QueueBottomOpts),
+    Compact(QueueCompactOpts),
+}
+
+/// sort the selected torrents, or the whole queue if none are selected
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "sort")]
+struct QueueSortOpts {
+    /// property to sort by: 'size', 'added', 'name' or 'category'
+    #[argh(option)]
+    by: String,
+    /// sort in descending order
+    #[argh(switch)]
+    reverse: bool,
+}
+
+/// move the selected torrents to the top of the queue
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "top")]
+struct QueueTopOpts {}
+
+/// move the selected torrents to the bottom of the queue
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "bottom")]
+struct QueueBottomOpts {}
+
+/// renumber the queue to remove gaps and duplicate positions
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "compact")]
+struct QueueCompactOpts {}
+
+/// start or stop the selected torrents and change their management mode
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "state")]
+struct StateOpts {
+    /// run state: 'started', 'forced' or 'stopped'
+    #[argh(option)]
+    run: Option<String>,
+    /// torrent management mode: 'auto' or 'manual'
+    #[argh(option)]
+    management: Option<String>,
+    /// stop condition: 'none', 'metadata' or 'checked'
+    #[argh(option)]
+    stop_condition: Option<String>,
+}
+
+/// rename the selected torrent, its root folder or individual files
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "rename")]
+struct RenameOpts {
+    /// new display name, '' restores the original name
+    #[argh(option)]
+    name: Option<String>,
+    /// new root folder name for a multi-file torrent
+    #[argh(option)]
+    root: Option<String>,
+    /// file rename as 'old=new' with paths relative to the save path, can be repeated
+    #[argh(option)]
+    file: Vec<String>,
+    /// also rename the files on disk
+    #[argh(switch)]
+    move_files: bool,
+}
+
+/// add, remove, deduplicate and reorder trackers of the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "trackers")]
+struct TrackersOpts {
+    /// also write the trackers to the announce-list of the torrent metadata
+    #[argh(switch)]
+    update_metadata: bool,
+    #[argh(subcommand)]
+    operation: TrackersOperationOpts,
+}
+
+/// Tracker operations
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum TrackersOperationOpts {
+    Add(TrackersAddOpts),
+    Remove(TrackersRemoveOpts),
+    Dedup(TrackersDedupOpts),
+    Collapse(TrackersCollapseOpts),
+    Split(TrackersSplitOpts),
+    Report(TrackersReportOpts),
+    Policy(TrackersPolicyOpts),
+}
+
+/// add a tracker to the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "add")]
+struct TrackersAddOpts {
+    /// tracker announce URL
+    #[argh(positional)]
+    url: String,
+    /// zero-based tier to add the tracker to, defaults to a new last tier
+    #[argh(option)]
+    tier: Option<usize>,
+}
+
+/// remove trackers containing a string from the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "remove")]
+struct TrackersRemoveOpts {
+    /// string contained in the tracker URLs to remove
+    #[argh(positional)]
+    pattern: String,
+}
+
+/// remove repeated trackers from the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "dedup")]
+struct TrackersDedupOpts {}
+
+/// move all trackers of the selected torrents into one tier
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "collapse")]
+struct TrackersCollapseOpts {}
+
+/// move each tracker of the selected torrents into its own tier
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "split")]
+struct TrackersSplitOpts {}
+
+/// report trackers used by the selected torrents without modifying the database
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "report")]
+struct TrackersReportOpts {}
+
+/// enforce a tracker policy from a JSON file on the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "policy")]
+struct TrackersPolicyOpts {
+    /// path to the policy file
+    #[argh(positional)]
+    policy_file: PathBuf,
+    /// report what would change without modifying the database
+    #[argh(switch)]
+    dry_run: bool,
+}
+
+/// replace the passkey in the announce URLs of a private tracker
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "passkey")]
+struct PasskeyOpts {
+    /// tracker domain, subdomains included
+    #[argh(option)]
+    host: String,
+    /// existing passkey, detected from the announce URLs if omitted
+    #[argh(option)]
+    old: Option<String>,
+    /// new passkey
+    #[argh(option)]
+    new: String,
+}
+
+/// add, remove or rewrite web seed URLs of the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "webseeds")]
+struct WebseedsOpts {
+    /// edit HTTP seeds (BEP 17) instead of web seeds (BEP 19)
+    #[argh(switch)]
+    http_seeds: bool,
+    #[argh(subcommand)]
+    operation: WebseedsOperationOpts,
+}
+
+/// Web seed operations
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum WebseedsOperationOpts {
+    Add(WebseedsAddOpts),
+    Remove(WebseedsRemoveOpts),
+    Replace(WebseedsReplaceOpts),
+}
+
+/// add a web seed to the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "add")]
+struct WebseedsAddOpts {
+    /// web seed URL
+    #[argh(positional)]
+    url: String,
+}
+
+/// remove web seeds containing a string from the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "remove")]
+struct WebseedsRemoveOpts {
+    /// string contained in the web seed URLs to remove
+    #[argh(positional)]
+    pattern: String,
+}
+
+/// replace a string in the web seed URLs of the selected torrents
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "replace")]
+struct WebseedsReplaceOpts {
+    /// existing string, e.g. 'https://old.mirror/'
+    #[argh(positional)]
+    old: String,
+    /// new string, e.g. 'https://new.mirror/'
+    #[argh(positional)]
+    new: String,
+}
+
+/// import torrents and their state from another client
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "import")]
+struct ImportOpts {
+    /// save path mapping as 'old=new', e.g. '/srv/torrents=D:\Torrents', can be repeated
+    #[argh(option)]
+    map: Vec<String>,
+    /// category for imported torrents without one
+    #[argh(option)]
+    category: Option<String>,
+    /// report what would be imported without modifying the database
+    #[argh(switch)]
+    dry_run: bool,
+    /// force using path slash '/' separators
+    #[argh(switch)]
+    use_unix_sep: bool,
+    /// force using Windows backslash '\' separators
+    #[argh(switch)]
+    use_win_sep: bool,
+    #[argh(subcommand)]
+    source: ImportSourceOpts,
+}
+
+/// Clients to import from
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum ImportSourceOpts {
+    Transmission(ImportTransmissionOpts),
+    Deluge(ImportDelugeOpts),
+    Rtorrent(ImportRtorrentOpts),
+}
+
+/// import from a Transmission config directory with torrents and resume directories
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "transmission")]
+struct ImportTransmissionOpts {
+    /// config directory, e.g. ~/.config/transmission-daemon
+    #[argh(positional)]
+    directory: PathBuf,
+}
+
+/// import from a Deluge config directory with a state directory, labels become categories
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "deluge")]
+struct ImportDelugeOpts {
+    /// config directory, e.g. ~/.config/deluge
+    #[argh(positional)]
+    directory: PathBuf,
+}
+
+/// import from an rTorrent session directory, custom1 labels become categories
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "rtorrent")]
+struct ImportRtorrentOpts {
+    /// session directory, e.g. ~/.session
+    #[argh(positional)]
+    directory: PathBuf,
+}
+
+/// export the selected torrents and their state to another client
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "export")]
+struct ExportOpts {
+    /// save path mapping as 'old=new', e.g. 'D:\Torrents=/srv/torrents', can be repeated
+    #[argh(option)]
+    map: Vec<String>,
+    /// force using path slash '/' separators
+    #[argh(switch)]
+    use_unix_sep: bool,
+    /// force using Windows backslash '\' separators
+    #[argh(switch)]
+    use_win_sep: bool,
+    #[argh(subcommand)]
+    format: ExportFormatOpts,
+}
+
+/// Clients to export to
+#[derive(Debug, FromArgs)]
+#[argh(subcommand)]
+enum ExportFormatOpts {
+    Transmission(ExportTransmissionOpts),
+    Deluge(ExportDelugeOpts),
+    Rtorrent(ExportRtorrentOpts),
+}
+
+/// export to a Transmission config directory, the category and tags become labels
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "transmission")]
+struct ExportTransmissionOpts {
+    /// new or empty directory to write to
+    #[argh(positional)]
+    directory: PathBuf,
+}
+
+/// export to a Deluge config directory, categories become labels
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "deluge")]
+struct ExportDelugeOpts {
+    /// new or empty directory to write to
+    #[argh(positional)]
+    directory: PathBuf,
+}
+
+/// export to an rTorrent session directory, categories become custom1 labels
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "rtorrent")]
+struct ExportRtorrentOpts {
+    /// new or empty directory to write to
+    #[argh(positional)]
+    directory: PathBuf,
+}
+
+/// merge the torrents of another torrents.db into this one, e.g. when consolidating seedboxes
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "merge")]
+struct MergeOpts {
+    /// path to the torrents.db to merge from
+    #[argh(positional)]
+    source: PathBuf,
+    /// how to resolve torrents in both databases: 'keep-target' (default), 'keep-source', 'newer'
+    /// or 'merge'
+    #[argh(option)]
+    conflict: Option<String>,
+    /// save path mapping as 'old=new' for incoming torrents, e.g. 'D:\Torrents=/srv/torrents', can
+    /// be repeated
+    #[argh(option)]
+    map: Vec<String>,
+    /// report what would be merged without modifying the database
+    #[argh(switch)]
+    dry_run: bool,
+    /// force using path slash '/' separators
+    #[argh(switch)]
+    use_unix_sep: bool,
+    /// force using Windows backslash '\' separators
+    #[argh(switch)]
+    use_win_sep: bool,
+}
+
+/// compare two torrents.db files, e.g. a backup with the database, without modifying either
+#[derive(Debug, FromArgs)]
+#[argh(subcommand, name = "diff")]
+struct DiffOpts {
+    /// torrents.db or backup to compare from
+    #[argh(positional)]
+    old: PathBuf,
+    /// torrents.db to compare to, defaults to the database in the config directory
+    #[argh(positional)]
+    new: Option<PathBuf>,
+    /// output the differences as JSON
+    #[argh(switch)]
+    json: bool,
+    /// file to write the differences to instead of printing them
+    #[argh(option)]
+    output: Option<PathBuf>,
+}
+
+/// Commands that run on their own instead of the save path and tracker url operations
+#[derive(Debug)]
+pub enum Command {
+    /// Check torrents.db for problems without modifying it
+    Doctor,
+    /// Reconcile target_save_path with the fastresume save path
+    Repair(SavePathRepair),
+    /// Move save and download paths to new drives or mount points
+    Migrate(Migration),
+    /// Set, clear or rename categories of the selected torrents
+    Category(CategoryChange),
+    /// Add, remove, rename or replace tags of the selected torrents
+    Tag(TagOperation),
+    /// Assign categories and tags to the selected torrents using rules
+    Rules(RuleSet),
+    /// Set seeding, rate and connection limits of the selected torrents
+    Limits(TorrentLimits),
+    /// Reorder the download queue
+    Queue(QueueOperation),
+    /// Start or stop the selected torrents and change their management mode
+    State(StateChange),
+    /// Rename the selected torrent, its root folder or individual files
+    Rename(RenameRequest),
+    /// Add, remove, deduplicate and reorder trackers of the selected torrents
+    Trackers(TrackerEdit),
+    /// Report trackers used by the selected torrents without modifying the database
+    TrackerReport,
+    /// Enforce a tracker policy on the selected torrents
+    TrackerPolicy(PolicyRun),
+    /// Replace the passkey in the announce URLs of a private tracker
+    Passkey(PasskeyChange),
+    /// Add, remove or rewrite web seed URLs of the selected torrents
+    Webseeds(WebSeedEdit),
+    /// Summarise transfer statistics of the selected torrents
+    Stats,
+    /// Set or add to transfer statistics of the selected torrents
+    StatsAdjust(StatsAdjustment),
+    /// Import torrents and their state from another client
+    Import(ImportRun),
+    /// Export the selected torrents and their state to another client
+    Export(ExportRun),
+    /// Merge the torrents of another torrents.db into the database
+    Merge(MergeRun),
+    /// Compare two torrents.db files
+    Diff(DiffRun),
+}
+
+impl Command {
+    /// Returns true if the command only operates on the torrents selected by the global filters
+    pub fn supports_filter(&self) -> bool {
+        !matches!(
+            self,
+            Command::Doctor | Command::Repair(_) | Command::Migrate(_) | Command::Import(_)
+        )
+    }
+
+    /// Returns true if the command prints machine-readable output, which any other output would
+    /// corrupt
+    pub fn is_quiet(&self) -> bool {
+        matches!(self, Command::Diff(run) if run.json && run.output.is_none())
+    }
+
+    /// Returns true if the command only inspects the database
+    pub fn is_read_only(&self) -> bool {
+        match self {
+            Command::Doctor | Command::TrackerReport | Command::Stats => true,
+            Command::Diff(_) => true,
+            // Export writes to its own directory and only reads the database
+            Command::Export(_) => true,
+            Command::Repair(_) => false,
+            // Without mappings, migrate only lists the path roots
+            Command::Migrate(migration) => migration.mappings.is_empty(),
+            Command::Category(_) | Command::Tag(_) => false,
+            Command::Rules(rule_set) => rule_set.dry_run,
+            Command::Limits(_) | Command::Queue(_) | Command::State(_) => false,
+            Command::Rename(_) | Command::Trackers(_) | Command::Passkey(_) => false,
+            Command::Webseeds(_) | Command::StatsAdjust(_) => false,
+            Command::TrackerPolicy(run) => run.dry_run,
+            Command::Import(run) => run.dry_run,
+            Command::Merge(run) => run.dry_run,
+        }
+    }
 }
 
 /// Application configuration generated from CLI arguments
@@ -58,8 +854,14 @@ pub struct Config {
     pub disable_backup: bool,
     /// Torrent save path information
     pub save_path: Option<SavePath>,
+    /// Torrent download path information
+    pub download_path: Option<SavePath>,
     /// Torrent tracker url information
     pub tracker_url: Option<TrackerUrl>,
+    /// Command to run instead of the save path and tracker url operations
+    pub command: Option<Command>,
+    /// Selects the torrents commands operate on
+    pub filter: TorrentFilter,
     /// Toggles verbose output
     pub verbose: bool,
 }
@@ -87,19 +889,12 @@ impl Config {
 
         let save_path = match (args.old_path, args.new_path) {
             (Some(old), Some(new)) => {
-                let separator: String;
-                if args.use_unix_sep {
-                    separator = '/'.to_string();
-                } else if args.use_win_sep {
-                    separator = '\\'.to_string();
-                } else {
-                    separator = MAIN_SEPARATOR.to_string();
-                }
+                let separator = get_separator(args.use_unix_sep, args.use_win_sep);
 
                 // qB saves a Unix-style path irrespective of OS, so we need to use that in some places
                 // Yes, even Windows network paths are saved that way e.g. \\server\folder -> //server/folder
-                let old_unix = old.replace('\\', "/");
-                let new_unix = new.replace('\\', "/");
+                let old_unix = fragment_to_portable(&old);
+                let new_unix = fragment_to_portable(&new);
 
                 // qB saves the path in a second location with OS-specific separators. It will be up to the user
                 // to pick the appropriate separator for the existing path to replace
@@ -109,6 +904,7 @@ impl Config {
                     old,
                     new,
                     separator,
+                    include_download_path: args.include_download_path,
                 })
             }
             (None, None) => None,
@@ -116,6 +912,20 @@ impl Config {
             (None, Some(_new)) => panic!("--old-path is missing!"),
         };
 
+        let download_path = match (args.old_download_path, args.new_download_path) {
+            (Some(old), Some(new)) => Some(SavePath {
+                old_unix: fragment_to_portable(&old),
+                new_unix: fragment_to_portable(&new),
+                old,
+                new,
+                separator: get_separator(args.use_unix_sep, args.use_win_sep),
+                include_download_path: true,
+            }),
+            (None, None) => None,
+            (Some(_old), None) => panic!("--new-download-path is missing!"),
+            (None, Some(_new)) => panic!("--old-download-path is missing!"),
+        };
+
         let tracker_url = match (args.old_tracker, args.new_tracker) {
             (Some(old), Some(new)) => Some(TrackerUrl { old, new }),
             (None, None) => None,
@@ -123,21 +933,460 @@ impl Config {
             (None, Some(_new)) => panic!("--old-tracker is missing!"),
         };
 
+        let command = match args.command {
+            Some(CLICommand::Doctor(_)) => Some(Command::Doctor),
+            Some(CLICommand::Repair(opts)) => {
+                let source = match opts.from.as_str() {
+                    "target" => RepairSource::TargetSavePath,
+                    "fastresume" => RepairSource::Fastresume,
+                    other => {
+                        return Err(format!(
+                            "unknown repair source {other}, expected 'target' or 'fastresume'"
+                        ))
+                    }
+                };
+                Some(Command::Repair(SavePathRepair {
+                    source,
+                    separator: get_separator(opts.use_unix_sep, opts.use_win_sep),
+                }))
+            }
+            Some(CLICommand::Migrate(opts)) => {
+                let mappings = parse_mappings(&opts.map)?;
+                Some(Command::Migrate(Migration {
+                    mappings,
+                    separator: get_separator(opts.use_unix_sep, opts.use_win_sep),
+                }))
+            }
+            Some(CLICommand::Category(opts)) => {
+                let operation = match opts.operation {
+                    CategoryOperationOpts::Set(set) => {
+                        check_category_name(&set.category)?;
+                        CategoryOperation::Set(set.category)
+                    }
+                    CategoryOperationOpts::Clear(_) => CategoryOperation::Clear,
+                    CategoryOperationOpts::Rename(rename) => {
+                        check_category_name(&rename.old)?;
+                        check_category_name(&rename.new)?;
+                        CategoryOperation::Rename {
+                            old: rename.old,
+                            new: rename.new,
+                        }
+                    }
+                };
+                Some(Command::Category(CategoryChange {
+                    operation,
+                    categories_file: opts
+                        .update_categories
+                        .then(|| qb_directory.join("categories.json")),
+                }))
+            }
+            Some(CLICommand::Tag(opts)) => {
+                let operation = match opts.operation {
+                    TagOperationOpts::Add(add) => TagOperation::Add(parse_tag_args(&add.tags)),
+                    TagOperationOpts::Remove(remove) => {
+                        TagOperation::Remove(parse_tag_args(&remove.tags))
+                    }
+                    TagOperationOpts::Rename(rename) => TagOperation::Rename {
+                        old: rename.old.trim().to_string(),
+                        new: rename.new.trim().to_string(),
+                    },
+                    TagOperationOpts::Set(set) => TagOperation::Set(parse_tag_args(&set.tags)),
+                };
+                if let TagOperation::Rename { new, .. } = &operation {
+                    if new.is_empty() || new.contains(',') {
+                        return Err(format!("{new:?} is not a valid tag name"));
+                    }
+                }
+                Some(Command::Tag(operation))
+            }
+            Some(CLICommand::Rules(opts)) => {
+                let rules = load_rules(&opts.rules_file).map_err(|err| {
+                    format!(
+                        "could not read rules from {:?}: {err}",
+                        opts.rules_file.display()
+                    )
+                })?;
+                Some(Command::Rules(RuleSet {
+                    rules,
+                    dry_run: opts.dry_run,
+                }))
+            }
+            Some(CLICommand::Limits(opts)) => {
+                let limits = TorrentLimits {
+                    ratio_limit: opts.ratio.as_deref().map(parse_ratio_limit).transpose()?,
+                    seeding_time_limit: opts
+                        .seeding_time
+                        .as_deref()
+                        .map(parse_time_limit)
+                        .transpose()?,
+                    inactive_seeding_time_limit: opts
+                        .inactive_seeding_time
+                        .as_deref()
+                        .map(parse_time_limit)
+                        .transpose()?,
+                    upload_rate_limit: opts
+                        .upload_limit
+                        .as_deref()
+                        .map(parse_rate_limit)
+                        .transpose()?,
+                    download_rate_limit: opts
+                        .download_limit
+                        .as_deref()
+                        .map(parse_rate_limit)
+                        .transpose()?,
+                    max_connections: opts
+                        .max_connections
+                        .as_deref()
+                        .map(parse_count_limit)
+                        .transpose()?,
+                    max_uploads: opts
+                        .max_uploads
+                        .as_deref()
+                        .map(parse_count_limit)
+                        .transpose()?,
+                };
+                if limits.is_empty() {
+                    return Err(String::from("limits requires at least one limit to set"));
+                }
+                Some(Command::Limits(limits))
+            }
+            Some(CLICommand::Queue(opts)) => {
+                let operation = match opts.operation {
+                    QueueOperationOpts::Sort(sort) => QueueOperation::Sort {
+                        key: SortKey::parse(&sort.by)?,
+                        reverse: sort.reverse,
+                    },
+                    QueueOperationOpts::Top(_) => QueueOperation::Top,
+                    QueueOperationOpts::Bottom(_) => QueueOperation::Bottom,
+                    QueueOperationOpts::Compact(_) => QueueOperation::Compact,
+                };
+                Some(Command::Queue(operation))
+            }
+            Some(CLICommand::State(opts)) => {
+                let change = StateChange {
+                    run_state: opts.run.as_deref().map(RunState::parse).transpose()?,
+                    management_mode: opts
+                        .management
+                        .as_deref()
+                        .map(ManagementMode::parse)
+                        .transpose()?,
+                    stop_condition: opts
+                        .stop_condition
+                        .as_deref()
+                        .map(parse_stop_condition)
+                        .transpose()?,
+                };
+                if change.is_empty() {
+                    return Err(String::from(
+                        "state requires at least one of --run, --management or --stop-condition",
+                    ));
+                }
+                Some(Command::State(change))
+            }
+            Some(CLICommand::Rename(opts)) => {
+                let mut files = Vec::new();
+                for file in opts.file {
+                    let Some((from, to)) = file.split_once('=') else {
+                        return Err(format!("file rename {file} is not in the form 'old=new'"));
+                    };
+                    files.push(FileRename {
+                        from: from.to_string(),
+                        to: to.to_string(),
+                    });
+                }
+                let request = RenameRequest {
+                    name: opts.name,
+                    root: opts.root,
+                    files,
+                    move_files: opts.move_files,
+                };
+                if request.is_empty() {
+                    return Err(String::from(
+                        "rename requires at least one of --name, --root or --file",
+                    ));
+                }
+                Some(Command::Rename(request))
+            }
+            Some(CLICommand::Trackers(TrackersOpts {
+                operation: TrackersOperationOpts::Report(_),
+                update_metadata,
+            })) => {
+                if update_metadata {
+                    return Err(String::from(
+                        "--update-metadata cannot be combined with trackers report",
+                    ));
+                }
+                Some(Command::TrackerReport)
+            }
+            Some(CLICommand::Trackers(TrackersOpts {
+                operation: TrackersOperationOpts::Policy(opts),
+                update_metadata,
+            })) => {
+                if update_metadata {
+                    return Err(String::from(
+                        "--update-metadata cannot be combined with trackers policy",
+                    ));
+                }
+                let policy = load_policy(&opts.policy_file).map_err(|err| {
+                    format!(
+                        "could not read tracker policy from {:?}: {err}",
+                        opts.policy_file.display()
+                    )
+                })?;
+                Some(Command::TrackerPolicy(PolicyRun {
+                    policy,
+                    dry_run: opts.dry_run,
+                }))
+            }
+            Some(CLICommand::Trackers(opts)) => {
+                let operation = match opts.operation {
+                    TrackersOperationOpts::Add(add) => {
+                        if add.url.trim().is_empty() {
+                            return Err(String::from("tracker URL cannot be empty"));
+                        }
+                        TrackerOperation::Add {
+                            url: add.url.trim().to_string(),
+                            tier: add.tier,
+                        }
+                    }
+                    TrackersOperationOpts::Remove(remove) => {
+                        if remove.pattern.is_empty() {
+                            return Err(String::from("tracker pattern cannot be empty"));
+                        }
+                        TrackerOperation::Remove(remove.pattern)
+                    }
+                    TrackersOperationOpts::Dedup(_) => TrackerOperation::Dedup,
+                    TrackersOperationOpts::Collapse(_) => TrackerOperation::Collapse,
+                    TrackersOperationOpts::Split(_) => TrackerOperation::Split,
+                    TrackersOperationOpts::Report(_) | TrackersOperationOpts::Policy(_) => {
+                        unreachable!()
+                    }
+                };
+                Some(Command::Trackers(TrackerEdit {
+                    operation,
+                    update_metadata: opts.update_metadata,
+                }))
+            }
+            Some(CLICommand::Passkey(opts)) => {
+                for key in opts.old.iter().chain([&opts.new]) {
+                    if key.is_empty() || key.contains(['/', '?', '&', '=', '#']) {
+                        return Err(format!("{key:?} is not a valid passkey"));
+                    }
+                }
+                Some(Command::Passkey(PasskeyChange {
+                    host: opts.host,
+                    old: opts.old,
+                    new: opts.new,
+                }))
+            }
+            Some(CLICommand::Webseeds(opts)) => {
+                let operation = match opts.operation {
+                    WebseedsOperationOpts::Add(add) => {
+                        if add.url.trim().is_empty() {
+                            return Err(String::from("web seed URL cannot be empty"));
+                        }
+                        WebSeedOperation::Add(add.url.trim().to_string())
+                    }
+                    WebseedsOperationOpts::Remove(remove) => {
+                        if remove.pattern.is_empty() {
+                            return Err(String::from("web seed pattern cannot be empty"));
+                        }
+                        WebSeedOperation::Remove(remove.pattern)
+                    }
+                    WebseedsOperationOpts::Replace(replace) => {
+                        if replace.old.is_empty() {
+                            return Err(String::from("string to replace cannot be empty"));
+                        }
+                        WebSeedOperation::Replace {
+                            old: replace.old,
+                            new: replace.new,
+                        }
+                    }
+                };
+                Some(Command::Webseeds(WebSeedEdit {
+                    operation,
+                    http_seeds: opts.http_seeds,
+                }))
+            }
+            Some(CLICommand::Stats(StatsOpts { operation: None })) => Some(Command::Stats),
+            Some(CLICommand::Stats(StatsOpts {
+                operation: Some(operation),
+            })) => {
+                let (mode, uploaded, downloaded, seeding_time, active_time) = match operation {
+                    StatsOperationOpts::Set(set) => (
+                        AdjustMode::Set,
+                        set.uploaded,
+                        set.downloaded,
+                        set.seeding_time,
+                        set.active_time,
+                    ),
+                    StatsOperationOpts::Add(add) => (
+                        AdjustMode::Add,
+                        add.uploaded,
+                        add.downloaded,
+                        add.seeding_time,
+                        add.active_time,
+                    ),
+                };
+                let adjustment = StatsAdjustment {
+                    mode,
+                    uploaded: uploaded.as_deref().map(parse_size).transpose()?,
+                    downloaded: downloaded.as_deref().map(parse_size).transpose()?,
+                    seeding_time: seeding_time.as_deref().map(parse_duration).transpose()?,
+                    active_time: active_time.as_deref().map(parse_duration).transpose()?,
+                };
+                if adjustment.is_empty() {
+                    return Err(String::from(
+                        "stats requires at least one counter to change",
+                    ));
+                }
+                if mode == AdjustMode::Set {
+                    let values = [
+                        adjustment.uploaded,
+                        adjustment.downloaded,
+                        adjustment.seeding_time,
+                        adjustment.active_time,
+                    ];
+                    if values.into_iter().flatten().any(|value| value < 0) {
+                        return Err(String::from("stats cannot be set to negative values"));
+                    }
+                    if let (Some(seeding_time), Some(active_time)) =
+                        (adjustment.seeding_time, adjustment.active_time)
+                    {
+                        if seeding_time > active_time {
+                            return Err(String::from("seeding time cannot exceed active time"));
+                        }
+                    }
+                }
+                Some(Command::StatsAdjust(adjustment))
+            }
+            Some(CLICommand::Import(opts)) => {
+                let mappings = parse_mappings(&opts.map)?;
+                if let Some(category) = opts.category.as_deref().filter(|c| !c.is_empty()) {
+                    check_category_name(category)?;
+                }
+                let source = match opts.source {
+                    ImportSourceOpts::Transmission(transmission) => {
+                        ImportSource::Transmission(transmission.directory)
+                    }
+                    ImportSourceOpts::Deluge(deluge) => ImportSource::Deluge(deluge.directory),
+                    ImportSourceOpts::Rtorrent(rtorrent) => {
+                        ImportSource::Rtorrent(rtorrent.directory)
+                    }
+                };
+                Some(Command::Import(ImportRun {
+                    source,
+                    migration: Migration {
+                        mappings,
+                        separator: get_separator(opts.use_unix_sep, opts.use_win_sep),
+                    },
+                    category: opts.category.filter(|category| !category.is_empty()),
+                    dry_run: opts.dry_run,
+                }))
+            }
+            Some(CLICommand::Export(opts)) => {
+                let mappings = parse_mappings(&opts.map)?;
+                let (format, directory) = match opts.format {
+                    ExportFormatOpts::Transmission(transmission) => {
+                        (ExportFormat::Transmission, transmission.directory)
+                    }
+                    ExportFormatOpts::Deluge(deluge) => (ExportFormat::Deluge, deluge.directory),
+                    ExportFormatOpts::Rtorrent(rtorrent) => {
+                        (ExportFormat::Rtorrent, rtorrent.directory)
+                    }
+                };
+                Some(Command::Export(ExportRun {
+                    format,
+                    directory,
+                    migration: Migration {
+                        mappings,
+                        separator: get_separator(opts.use_unix_sep, opts.use_win_sep),
+                    },
+                }))
+            }
+            Some(CLICommand::Merge(opts)) => {
+                let mappings = parse_mappings(&opts.map)?;
+                Some(Command::Merge(MergeRun {
+                    source: opts.source,
+                    conflict: opts
+                        .conflict
+                        .as_deref()
+                        .map(ConflictMode::parse)
+                        .transpose()?
+                        .unwrap_or(ConflictMode::KeepTarget),
+                    migration: Migration {
+                        mappings,
+                        separator: get_separator(opts.use_unix_sep, opts.use_win_sep),
+                    },
+                    dry_run: opts.dry_run,
+                }))
+            }
+            Some(CLICommand::Diff(opts)) => Some(Command::Diff(DiffRun {
+                old: opts.old,
+                new: opts.new,
+                json: opts.json,
+                output: opts.output,
+            })),
+            None => None,
+        };
+
+        let filter = TorrentFilter {
+            hashes: args.only_hash,
+            category: args.only_category,
+            tag: args.only_tag,
+            tracker: args.only_tracker,
+            tracker_host: args.only_tracker_host,
+            private: match (args.only_private, args.only_public) {
+                (true, true) => {
+                    return Err(String::from(
+                        "--only-private and --only-public cannot be combined",
+                    ))
+                }
+                (true, false) => Some(true),
+                (false, true) => Some(false),
+                (false, false) => None,
+            },
+            save_path: args.only_path.as_deref().map(QbPath::parse),
+            name: args.only_name,
+        };
+
+        if command.is_some()
+            && (save_path.is_some() || download_path.is_some() || tracker_url.is_some())
+        {
+            return Err(String::from(
+                "commands cannot be combined with path or tracker replacement",
+            ));
+        }
+
+        // Filters are rejected rather than ignored, so a filtered run never touches every torrent
+        if !filter.is_empty() && !command.as_ref().is_some_and(Command::supports_filter) {
+            return Err(String::from(
+                "the --only-* options cannot be combined with doctor, repair, migrate, import or path and tracker replacement",
+            ));
+        }
+
         let config = Config {
             qb_directory,
             db_file,
             disable_backup: args.disable_backup,
             save_path,
+            download_path,
             tracker_url,
+            command,
+            filter,
             verbose: args.verbose,
         };
 
-        if config.verbose {
+        // JSON printed to stdout must not be preceded by anything else
+        if config.verbose && !config.command.as_ref().is_some_and(Command::is_quiet) {
             println!("Verbose output enabled");
             println!("Using {:?} as qB directory", config.qb_directory.display());
             println!("Using {:?} as qB database", config.db_file.display());
             println!("Save path: {:?}", config.save_path);
+            println!("Download path: {:?}", config.download_path);
             println!("Tracker url: {:?}", config.tracker_url);
+            println!("Command: {:?}", config.command);
+            println!("Filter: {:?}", config.filter);
         }
 
         Ok(config)
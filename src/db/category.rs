@@ -0,0 +1,288 @@
+//! Tools for managing torrent categories
+
+use crate::db::filter::{fetch_torrents, TorrentFilter};
+use rusqlite::{named_params, Connection};
+use serde::Serialize;
+use serde_json::{Map, Value};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+/// Category operation to perform
+#[derive(Debug)]
+pub enum CategoryOperation {
+    /// Set the category of the selected torrents
+    Set(String),
+    /// Remove the category from the selected torrents
+    Clear,
+    /// Rename a category, including its subcategories, on the selected torrents
+    Rename {
+        /// Existing category name
+        old: String,
+        /// New category name
+        new: String,
+    },
+}
+
+/// Category change information
+#[derive(Debug)]
+pub struct CategoryChange {
+    /// The operation to perform
+    pub operation: CategoryOperation,
+    /// Path to qB's categories.json, updated alongside the database when given
+    pub categories_file: Option<PathBuf>,
+}
+
+/// Returns an error if qB would reject the category name
+///
+/// Like qB's `isValidCategoryName`, a name must not be empty, start or end with '/', or contain an
+/// empty subcategory such as `movies//hd`.
+pub fn check_category_name(name: &str) -> Result<(), String> {
+    if name.is_empty() || name.starts_with('/') || name.ends_with('/') || name.contains("//") {
+        return Err(format!("{name:?} is not a valid category name"));
+    }
+    Ok(())
+}
+
+/// Returns the renamed category if `category` is `old` or one of its subcategories
+fn rename_category(category: &str, old: &str, new: &str) -> Option<String> {
+    if category == old {
+        Some(new.to_string())
+    } else {
+        category
+            .strip_prefix(old)
+            .filter(|rest| rest.starts_with('/'))
+            .map(|rest| format!("{new}{rest}"))
+    }
+}
+
+/// Sets, clears or renames the category of every torrent matching the filter
+///
+/// Renaming with an empty filter renames the category across all torrents. qB subcategories such as
+/// `tv/hd` are renamed along with their parent category.
+///
+/// If a categories.json file is given, it is updated too: set categories are added if they are
+/// missing, and renamed categories are moved to their new name with any save path ending in the old
+/// category name rewritten to the new name. A category still used by torrents outside the filter
+/// keeps its entry, and renaming onto an existing category merges into it without changing its
+/// options. Files on disk are not moved.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::category::{change_category, CategoryChange, CategoryOperation};
+/// let category_change = CategoryChange {
+///     operation: CategoryOperation::Rename {
+///         old: String::from("tv"),
+///         new: String::from("shows"),
+///     },
+///     categories_file: None,
+/// };
+/// change_category(&connection, category_change, &TorrentFilter::default(), false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the torrent hash and new category for every updated
+/// torrent.
+pub fn change_category(
+    db: &Connection,
+    category_change: CategoryChange,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    match &category_change.operation {
+        CategoryOperation::Set(category) => println!("Category: setting category {}", category),
+        CategoryOperation::Clear => println!("Category: clearing categories"),
+        CategoryOperation::Rename { old, new } => {
+            println!("Category: renaming {} to {}", old, new)
+        }
+    }
+
+    let torrents = fetch_torrents(db, filter)?;
+
+    let mut num_updated = 0;
+    let mut num_auto_managed = 0;
+    for torrent in torrents {
+        let current = torrent.data.category.as_deref().unwrap_or_default();
+        let category = match &category_change.operation {
+            CategoryOperation::Set(category) => Some(category.clone()),
+            CategoryOperation::Clear => None,
+            CategoryOperation::Rename { old, new } => match rename_category(current, old, new) {
+                Some(category) => Some(category),
+                None => continue,
+            },
+        };
+
+        if category.as_deref().unwrap_or_default() == current {
+            continue;
+        }
+
+        // An empty target_save_path means qB derives the save path from the category
+        if torrent
+            .data
+            .target_save_path
+            .as_deref()
+            .unwrap_or_default()
+            .is_empty()
+        {
+            num_auto_managed += 1;
+        }
+
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET category = :category
+                WHERE id = :id
+                RETURNING torrent_id;",
+        )?;
+        update_stmt.query_row(
+            named_params! {":category": category, ":id": torrent.data.id},
+            |row| {
+                let updated_row_id = row.get::<usize, String>(0)?;
+
+                if verbose {
+                    println!("Category: updated category for {}", updated_row_id);
+                    println!(
+                        "{}: new category is {}",
+                        updated_row_id,
+                        category.as_deref().unwrap_or("(none)")
+                    );
+                }
+
+                num_updated += 1;
+
+                Ok(())
+            },
+        )?;
+    }
+
+    match num_updated {
+        0 => println!("Category: no torrents were updated"),
+        1 => println!("Category: 1 torrent was updated"),
+        _ => println!("Category: {} torrents were updated", num_updated),
+    }
+
+    if num_auto_managed > 0 {
+        println!(
+            "Category: {} updated torrents use automatic torrent management, qB will move them to their category's save path",
+            num_auto_managed
+        );
+    }
+
+    if let Some(categories_file) = &category_change.categories_file {
+        // Torrents outside the filter may still use a renamed category
+        let mut used_stmt = db.prepare(
+            "SELECT DISTINCT category FROM torrents WHERE category IS NOT NULL AND category != ''",
+        )?;
+        let used_categories = used_stmt
+            .query_map([], |row| row.get::<usize, String>(0))?
+            .collect::<Result<BTreeSet<String>, _>>()?;
+        update_categories_file(
+            categories_file,
+            &category_change.operation,
+            &used_categories,
+            verbose,
+        )?;
+    }
+
+    Ok(())
+}
+
+/// Applies a category operation to qB's categories.json
+///
+/// A renamed category that is still used by a torrent, or has a subcategory that is, keeps its
+/// entry. Renaming onto an existing category merges into it, keeping the existing options.
+fn update_categories_file(
+    categories_file: &PathBuf,
+    operation: &CategoryOperation,
+    used_categories: &BTreeSet<String>,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut categories: Map<String, Value> = match fs::read_to_string(categories_file) {
+        Ok(contents) => serde_json::from_str(&contents)?,
+        Err(err) if err.kind() == std::io::ErrorKind::NotFound => Map::new(),
+        Err(err) => return Err(err.into()),
+    };
+
+    match operation {
+        CategoryOperation::Set(category) => {
+            if categories.contains_key(category) {
+                return Ok(());
+            }
+            let mut options = Map::new();
+            options.insert(String::from("save_path"), Value::String(String::new()));
+            categories.insert(category.clone(), Value::Object(options));
+
+            if verbose {
+                println!(
+                    "Category: added {} to {:?}",
+                    category,
+                    categories_file.display()
+                );
+            }
+        }
+        CategoryOperation::Clear => return Ok(()),
+        CategoryOperation::Rename { old, new } => {
+            let names: Vec<String> = categories.keys().cloned().collect();
+            for name in names {
+                let Some(renamed) = rename_category(&name, old, new) else {
+                    continue;
+                };
+                let still_used = used_categories
+                    .iter()
+                    .any(|used| rename_category(used, &name, &name).is_some());
+                let mut options = match still_used {
+                    true => categories[&name].clone(),
+                    false => categories.remove(&name).unwrap(),
+                };
+
+                // A save path named after the category follows the rename
+                if let Some(Value::String(save_path)) = options.get_mut("save_path") {
+                    let old_name = name.rsplit('/').next().unwrap_or(&name);
+                    let new_name = renamed.rsplit('/').next().unwrap_or(&renamed);
+                    let trimmed = save_path.trim_end_matches(['/', '\\']);
+                    if let Some(parent) = trimmed.strip_suffix(old_name) {
+                        if parent.is_empty() || parent.ends_with(['/', '\\']) {
+                            *save_path = format!("{parent}{new_name}");
+                        }
+                    }
+                }
+
+                if verbose {
+                    let action = match (categories.contains_key(&renamed), still_used) {
+                        (true, _) => "merged",
+                        (false, true) => "copied",
+                        (false, false) => "renamed",
+                    };
+                    println!(
+                        "Category: {} {} to {} in {:?}",
+                        action,
+                        name,
+                        renamed,
+                        categories_file.display()
+                    );
+                }
+                match (categories.get_mut(&renamed), options) {
+                    // The existing category keeps its options, missing ones are taken from the old one
+                    (Some(Value::Object(existing)), Value::Object(options)) => {
+                        for (key, value) in options {
+                            existing.entry(key).or_insert(value);
+                        }
+                    }
+                    (Some(_), _) => {}
+                    (None, options) => {
+                        categories.insert(renamed, options);
+                    }
+                }
+            }
+        }
+    }
+
+    // qB writes categories.json with four space indentation
+    let mut contents = Vec::new();
+    let formatter = serde_json::ser::PrettyFormatter::with_indent(b"    ");
+    let mut serializer = serde_json::Serializer::with_formatter(&mut contents, formatter);
+    categories.serialize(&mut serializer)?;
+    fs::write(categories_file, contents)?;
+
+    Ok(())
+}
@@ -0,0 +1,267 @@
+//! Tools for resetting and adjusting torrent transfer statistics
+
+use crate::db::filter::{fetch_torrents, TorrentFilter};
+use crate::db::stats::format_duration;
+use crate::db::tracker_report::format_size;
+use rusqlite::{named_params, Connection};
+use std::error::Error;
+
+/// How adjustment values are applied
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AdjustMode {
+    /// Replace the counters with the values
+    Set,
+    /// Add the values to the counters, negative values subtract
+    Add,
+}
+
+/// Transfer statistics adjustment, unset counters are left unchanged
+#[derive(Debug)]
+pub struct StatsAdjustment {
+    /// How the values are applied
+    pub mode: AdjustMode,
+    /// Total uploaded in bytes
+    pub uploaded: Option<i64>,
+    /// Total downloaded in bytes
+    pub downloaded: Option<i64>,
+    /// Seeding time in seconds
+    pub seeding_time: Option<i64>,
+    /// Active time in seconds
+    pub active_time: Option<i64>,
+}
+
+impl StatsAdjustment {
+    /// Returns true if no counters are adjusted
+    pub fn is_empty(&self) -> bool {
+        self.uploaded.is_none()
+            && self.downloaded.is_none()
+            && self.seeding_time.is_none()
+            && self.active_time.is_none()
+    }
+}
+
+/// Parses an amount of data such as `500M`, `1.5G` or `-2G` into bytes
+///
+/// Plain numbers are bytes, and `K`, `M`, `G` and `T` suffixes are powers of 1024.
+pub fn parse_size(value: &str) -> Result<i64, String> {
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((index, 'K' | 'k')) => (&value[..index], 1u64 << 10),
+        Some((index, 'M' | 'm')) => (&value[..index], 1 << 20),
+        Some((index, 'G' | 'g')) => (&value[..index], 1 << 30),
+        Some((index, 'T' | 't')) => (&value[..index], 1 << 40),
+        _ => (value, 1),
+    };
+    match number.parse::<f64>() {
+        Ok(size) if size.is_finite() => {
+            let bytes = (size * multiplier as f64).round();
+            // i64::MAX rounds up to 2^63 as a float, so the upper bound is exclusive
+            if bytes >= i64::MIN as f64 && bytes < i64::MAX as f64 {
+                Ok(bytes as i64)
+            } else {
+                Err(format!("amount of data {value} is too large"))
+            }
+        }
+        _ => Err(format!(
+            "{value} is not a valid amount of data, e.g. 500M or 1.5G"
+        )),
+    }
+}
+
+/// Parses a duration such as `3600`, `90m`, `12h`, `30d` or `-1w` into seconds
+///
+/// Plain numbers are seconds, and `s`, `m`, `h`, `d` and `w` suffixes are accepted.
+pub fn parse_duration(value: &str) -> Result<i64, String> {
+    let (number, multiplier) = match value.char_indices().last() {
+        Some((index, 's')) => (&value[..index], 1),
+        Some((index, 'm')) => (&value[..index], 60),
+        Some((index, 'h')) => (&value[..index], 60 * 60),
+        Some((index, 'd')) => (&value[..index], 60 * 60 * 24),
+        Some((index, 'w')) => (&value[..index], 60 * 60 * 24 * 7),
+        _ => (value, 1),
+    };
+    match number.parse::<i64>() {
+        Ok(duration) => duration
+            .checked_mul(multiplier)
+            .ok_or_else(|| format!("duration {value} is too large")),
+        _ => Err(format!("{value} is not a valid duration, e.g. 90m or 30d")),
+    }
+}
+
+/// Applies an adjustment to a counter, returning `None` if the result would be negative
+fn adjust(current: i64, value: Option<i64>, mode: AdjustMode) -> Option<i64> {
+    let new = match (value, mode) {
+        (None, _) => current,
+        (Some(value), AdjustMode::Set) => value,
+        (Some(value), AdjustMode::Add) => current.checked_add(value)?,
+    };
+    (new >= 0).then_some(new)
+}
+
+/// Sets or adds to the transfer statistics of every torrent matching the filter
+///
+/// The counters are `total_uploaded`, `total_downloaded`, `seeding_time` and `active_time` in
+/// `libtorrent_resume_data`. A torrent is skipped if a counter would become negative, or if its
+/// seeding time would exceed its active time, as seeding time is part of active time.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::counters::{adjust_stats, parse_size, AdjustMode, StatsAdjustment};
+/// let adjustment = StatsAdjustment {
+///     mode: AdjustMode::Add,
+///     uploaded: Some(parse_size("1.5G")?),
+///     downloaded: None,
+///     seeding_time: None,
+///     active_time: None,
+/// };
+/// adjust_stats(&connection, adjustment, &filter, false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the torrent hash and new counters for every updated
+/// torrent.
+pub fn adjust_stats(
+    db: &Connection,
+    adjustment: StatsAdjustment,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    match adjustment.mode {
+        AdjustMode::Set => println!("Stats: setting transfer statistics"),
+        AdjustMode::Add => println!("Stats: adjusting transfer statistics"),
+    }
+
+    let torrents = fetch_torrents(db, filter)?;
+
+    let mut num_updated = 0;
+    let mut num_skipped = 0;
+    for torrent in torrents {
+        let mut libtorrent_resume_data = torrent.fastresume;
+        let mode = adjustment.mode;
+
+        let counters = (
+            adjust(
+                libtorrent_resume_data.total_uploaded as i64,
+                adjustment.uploaded,
+                mode,
+            ),
+            adjust(
+                libtorrent_resume_data.total_downloaded as i64,
+                adjustment.downloaded,
+                mode,
+            ),
+            adjust(
+                libtorrent_resume_data.seeding_time as i64,
+                adjustment.seeding_time,
+                mode,
+            ),
+            adjust(
+                libtorrent_resume_data.active_time,
+                adjustment.active_time,
+                mode,
+            ),
+        );
+        let (Some(uploaded), Some(downloaded), Some(seeding_time), Some(active_time)) = counters
+        else {
+            println!(
+                "Stats: skipping {}, a counter would become negative",
+                torrent.data.torrent_id
+            );
+            num_skipped += 1;
+            continue;
+        };
+        if seeding_time > active_time {
+            println!(
+                "Stats: skipping {}, seeding time {} would exceed active time {}",
+                torrent.data.torrent_id,
+                format_duration(seeding_time as u64),
+                format_duration(active_time as u64)
+            );
+            num_skipped += 1;
+            continue;
+        }
+
+        let before = (
+            libtorrent_resume_data.total_uploaded,
+            libtorrent_resume_data.total_downloaded,
+            libtorrent_resume_data.seeding_time,
+            libtorrent_resume_data.active_time,
+        );
+        libtorrent_resume_data.total_uploaded = uploaded as u64;
+        libtorrent_resume_data.total_downloaded = downloaded as u64;
+        libtorrent_resume_data.seeding_time = seeding_time as u64;
+        libtorrent_resume_data.active_time = active_time;
+        let after = (
+            libtorrent_resume_data.total_uploaded,
+            libtorrent_resume_data.total_downloaded,
+            libtorrent_resume_data.seeding_time,
+            libtorrent_resume_data.active_time,
+        );
+        if before == after {
+            continue;
+        }
+
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET libtorrent_resume_data = :lrd
+                WHERE id = :id
+                RETURNING torrent_id;",
+        )?;
+        update_stmt.query_row(
+            named_params! {
+                ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?,
+                ":id": torrent.data.id,
+            },
+            |row| {
+                let updated_row_id = row.get::<usize, String>(0)?;
+
+                if verbose {
+                    println!("Stats: updated transfer statistics for {}", updated_row_id);
+                    println!(
+                        "{}: uploaded {}, downloaded {}, seeding {}, active {}",
+                        updated_row_id,
+                        format_size(libtorrent_resume_data.total_uploaded),
+                        format_size(libtorrent_resume_data.total_downloaded),
+                        format_duration(libtorrent_resume_data.seeding_time),
+                        format_duration(libtorrent_resume_data.active_time as u64)
+                    );
+                }
+
+                num_updated += 1;
+
+                Ok(())
+            },
+        )?;
+    }
+
+    match num_skipped {
+        0 => {}
+        1 => println!("Stats: 1 torrent was skipped"),
+        _ => println!("Stats: {} torrents were skipped", num_skipped),
+    }
+    match num_updated {
+        0 => println!("Stats: no torrents were updated"),
+        1 => println!("Stats: 1 torrent was updated"),
+        _ => println!("Stats: {} torrents were updated", num_updated),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_sizes() {
+        assert_eq!(parse_size("500"), Ok(500));
+        assert_eq!(parse_size("1.5K"), Ok(1536));
+        assert_eq!(parse_size("-2m"), Ok(-2 << 20));
+        assert_eq!(parse_size("1T"), Ok(1 << 40));
+        assert!(parse_size("8388608T").is_err());
+        assert!(parse_size("1e300G").is_err());
+        assert!(parse_size("-1e300").is_err());
+        assert!(parse_size("inf").is_err());
+        assert!(parse_size("NaN").is_err());
+        assert!(parse_size("G").is_err());
+    }
+}
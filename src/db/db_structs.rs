@@ -1,6 +1,8 @@
 //! Structs for qBittorrent SQLite data
 
+use serde_bencode::value::Value;
 use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 
 /// qB SQLite data
 ///
@@ -45,8 +47,10 @@ pub struct DatabaseData {
     ///
     /// See "FastresumeData" for deserialized contents
     pub libtorrent_resume_data: Vec<u8>,
-    /// Binary blob containing metadata
-    pub metadata: Vec<u8>,
+    /// Binary blob containing metadata, missing for magnet links without metadata
+    ///
+    /// See "TorrentMetadata" for deserialized contents
+    pub metadata: Option<Vec<u8>>,
 }
 
 /// qB libtorrent fastresume data
@@ -85,8 +89,8 @@ pub struct FastresumeData {
     pub file_priority: Option<Vec<u8>>,
     /// Date torrent was finish
     pub finished_time: i64,
-    /// httpseed URLs used by torrent
-    pub httpseeds: Vec<u8>,
+    /// HTTP seed URLs (BEP 17) used by torrent
+    pub httpseeds: Vec<String>,
     // Parsing this is annoying right now, skipping it since it's not important
     // /// i2p
     // pub i2p: i64,
@@ -96,7 +100,8 @@ pub struct FastresumeData {
     pub info_hash: Vec<u8>,
     /// V2 info hash for torrent
     #[serde(rename = "info-hash2")]
-    pub info_hash2: String,
+    #[serde(with = "serde_bytes")]
+    pub info_hash2: Vec<u8>,
     /// Date last downloaded
     pub last_download: i64,
     /// Date last seen complete
@@ -106,6 +111,8 @@ pub struct FastresumeData {
     /// libtorrent version
     #[serde(rename = "libtorrent-version")]
     pub libtorrent_version: String,
+    /// Renamed file paths relative to the save path, one per file, only present if a file was renamed
+    pub mapped_files: Option<Vec<String>>,
     /// Max number of active connections
     pub max_connections: i64,
     /// Max number of upload slots
@@ -156,7 +163,7 @@ pub struct FastresumeData {
     pub upload_mode: i64,
     /// Upload rate limit set on torrent
     pub upload_rate_limit: i64,
-    /// List of url-seed URLs for torrent
+    /// Web seed URLs (BEP 19) used by torrent
     #[serde(rename = "url-list")]
     pub url_list: Vec<String>,
 }
@@ -170,6 +177,8 @@ pub struct FetchedPathData {
     pub torrent_id: String,
     /// Save path for torrent content
     pub target_save_path: Option<String>,
+    /// Download path, used for incomplete download directory
+    pub download_path: Option<String>,
     /// Binary blob containing fastresume data
     pub libtorrent_resume_data: Vec<u8>,
 }
@@ -184,3 +193,293 @@ pub struct FetchedLibtorrentResumeData {
     ///Binary blob containing fastresume data
     pub libtorrent_resume_data: Vec<u8>,
 }
+
+/// Torrent metadata
+///
+/// This data comes from the metadata column in the "torrents" table of the SQLite database. qB stores
+/// the torrent's info dictionary along with a few of the optional top-level .torrent fields.
+#[derive(Serialize, Deserialize, Debug)]
+pub struct TorrentMetadata {
+    /// Torrent info dictionary, kept as a raw bencode value so it re-encodes to the same bytes
+    pub info: Value,
+    /// Main tracker URL, qB does not usually store it in the metadata
+    pub announce: Option<String>,
+    /// Tracker tiers, qB does not usually store them in the metadata
+    #[serde(rename = "announce-list")]
+    pub announce_list: Option<Vec<Vec<String>>>,
+    /// Torrent comment
+    pub comment: Option<String>,
+    /// Program used to create the torrent
+    #[serde(rename = "created by")]
+    pub created_by: Option<String>,
+    /// Date the torrent was created
+    #[serde(rename = "creation date")]
+    pub creation_date: Option<i64>,
+}
+
+impl TorrentMetadata {
+    /// Returns the hex-encoded v1 info hash, the SHA-1 digest of the bencoded info dictionary
+    pub fn info_hash(&self) -> Result<String, serde_bencode::Error> {
+        let info = serde_bencode::to_bytes(&self.info)?;
+        Ok(sha1_smol::Sha1::from(info).digest().to_string())
+    }
+
+    /// Returns the hex-encoded v2 info hash, the SHA-256 digest of the bencoded info dictionary
+    pub fn info_hash_v2(&self) -> Result<String, serde_bencode::Error> {
+        let info = serde_bencode::to_bytes(&self.info)?;
+        Ok(hex(&Sha256::digest(info)))
+    }
+
+    /// Returns true if the torrent has v1 piece hashes, either a v1 or a hybrid torrent
+    pub fn has_v1(&self) -> bool {
+        matches!(&self.info, Value::Dict(info) if info.contains_key(b"pieces".as_slice()))
+    }
+
+    /// Returns true if the torrent has a v2 file tree, either a v2 or a hybrid torrent
+    pub fn has_v2(&self) -> bool {
+        matches!(&self.info, Value::Dict(info) if matches!(info.get(b"meta version".as_slice()), Some(Value::Int(2))))
+    }
+
+    /// Returns the id qB stores the torrent under
+    ///
+    /// qB uses the v1 info hash of v1 and hybrid torrents, and the v2 info hash truncated to 20 bytes
+    /// for v2-only torrents.
+    pub fn torrent_id(&self) -> Result<String, serde_bencode::Error> {
+        match self.has_v1() {
+            true => self.info_hash(),
+            false => Ok(self.info_hash_v2()?[..40].to_string()),
+        }
+    }
+
+    /// Returns true if the info dictionary marks the torrent as private
+    pub fn is_private(&self) -> bool {
+        match &self.info {
+            Value::Dict(info) => matches!(info.get(b"private".as_slice()), Some(Value::Int(1))),
+            _ => false,
+        }
+    }
+
+    /// Returns the original path of every file relative to the save path, using '/' separators
+    ///
+    /// Multi-file torrents have their files below a root folder named after the torrent, and
+    /// single-file torrents consist of a file named after the torrent.
+    pub fn file_paths(&self) -> Vec<String> {
+        let Value::Dict(info) = &self.info else {
+            return Vec::new();
+        };
+        let name = match info.get(b"name".as_slice()) {
+            Some(Value::Bytes(name)) => String::from_utf8_lossy(name).into_owned(),
+            _ => return Vec::new(),
+        };
+        match info.get(b"files".as_slice()) {
+            Some(Value::List(files)) => files
+                .iter()
+                .filter_map(|file| match file {
+                    Value::Dict(file) => match file.get(b"path".as_slice()) {
+                        Some(Value::List(path)) => Some(path),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .map(|path| {
+                    let mut components = vec![name.clone()];
+                    components.extend(path.iter().filter_map(|component| match component {
+                        Value::Bytes(component) => {
+                            Some(String::from_utf8_lossy(component).into_owned())
+                        }
+                        _ => None,
+                    }));
+                    components.join("/")
+                })
+                .collect(),
+            _ => vec![name],
+        }
+    }
+
+    /// Returns the torrent name from the info dictionary
+    pub fn name(&self) -> String {
+        match &self.info {
+            Value::Dict(info) => match info.get(b"name".as_slice()) {
+                Some(Value::Bytes(name)) => String::from_utf8_lossy(name).into_owned(),
+                _ => String::new(),
+            },
+            _ => String::new(),
+        }
+    }
+
+    /// Returns the size of each piece in bytes, the last piece may be shorter
+    pub fn piece_length(&self) -> i64 {
+        match &self.info {
+            Value::Dict(info) => match info.get(b"piece length".as_slice()) {
+                Some(Value::Int(piece_length)) => *piece_length,
+                _ => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    /// Returns the number of pieces, from the SHA-1 piece hashes of the info dictionary
+    pub fn num_pieces(&self) -> usize {
+        match &self.info {
+            Value::Dict(info) => match info.get(b"pieces".as_slice()) {
+                Some(Value::Bytes(pieces)) => pieces.len() / 20,
+                _ => 0,
+            },
+            _ => 0,
+        }
+    }
+
+    /// Returns the total size of the torrent's files in bytes, from the `length` or `files` fields
+    pub fn total_size(&self) -> i64 {
+        let Value::Dict(info) = &self.info else {
+            return 0;
+        };
+        match (
+            info.get(b"length".as_slice()),
+            info.get(b"files".as_slice()),
+        ) {
+            (Some(Value::Int(length)), _) => *length,
+            (_, Some(Value::List(files))) => files
+                .iter()
+                .filter_map(|file| match file {
+                    Value::Dict(file) => match file.get(b"length".as_slice()) {
+                        Some(Value::Int(length)) => Some(*length),
+                        _ => None,
+                    },
+                    _ => None,
+                })
+                .sum(),
+            _ => 0,
+        }
+    }
+}
+
+/// Hex-encodes a hash digest
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{byte:02x}")).collect()
+}
+
+/// Replaces the `announce` and `announce-list` of bencoded metadata with the given tracker tiers
+///
+/// The metadata is edited as a raw bencode dictionary, so keys [`TorrentMetadata`] does not know
+/// about, such as the `piece layers` of v2 torrents or `url-list` web seeds, are kept. Without
+/// trackers both keys are removed. Returns `None` if the metadata already has these trackers.
+pub fn replace_announce(
+    metadata: &[u8],
+    trackers: &[Vec<String>],
+) -> Result<Option<Vec<u8>>, serde_bencode::Error> {
+    let Value::Dict(mut dict) = serde_bencode::from_bytes::<Value>(metadata)? else {
+        return Err(serde_bencode::Error::Custom(String::from(
+            "metadata is not a dictionary",
+        )));
+    };
+
+    let announce = trackers
+        .iter()
+        .flatten()
+        .next()
+        .map(|tracker| Value::Bytes(tracker.as_bytes().to_vec()));
+    let announce_list = (!trackers.is_empty()).then(|| {
+        Value::List(
+            trackers
+                .iter()
+                .map(|tier| {
+                    Value::List(
+                        tier.iter()
+                            .map(|tracker| Value::Bytes(tracker.as_bytes().to_vec()))
+                            .collect(),
+                    )
+                })
+                .collect(),
+        )
+    });
+
+    let mut changed = false;
+    for (key, value) in [
+        (b"announce".to_vec(), announce),
+        (b"announce-list".to_vec(), announce_list),
+    ] {
+        if dict.get(&key) == value.as_ref() {
+            continue;
+        }
+        changed = true;
+        match value {
+            Some(value) => dict.insert(key, value),
+            None => dict.remove(&key),
+        };
+    }
+
+    match changed {
+        true => Ok(Some(serde_bencode::to_bytes(&Value::Dict(dict))?)),
+        false => Ok(None),
+    }
+}
+
+impl FastresumeData {
+    /// Returns the hex-encoded info hash stored in the fastresume data
+    pub fn info_hash_hex(&self) -> String {
+        hex(&self.info_hash)
+    }
+
+    /// Returns the id qB stores the torrent under, see [`TorrentMetadata::torrent_id`]
+    ///
+    /// libtorrent writes an all-zero v1 info hash for v2-only torrents.
+    pub fn torrent_id(&self) -> String {
+        match self.info_hash.iter().any(|byte| *byte != 0) {
+            true => self.info_hash_hex(),
+            false => hex(&self.info_hash2[..self.info_hash2.len().min(20)]),
+        }
+    }
+}
+
+/// A subset of database columns needed for database health checks
+#[derive(Serialize, Deserialize, Debug)]
+pub struct FetchedHealthData {
+    /// Torrent database row id
+    pub id: u64,
+    /// Torrent hash id
+    pub torrent_id: String,
+    /// Queue position for torrent
+    pub queue_position: i64,
+    /// Save path for torrent content
+    pub target_save_path: Option<String>,
+    /// Download path, used for incomplete download directory
+    pub download_path: Option<String>,
+    /// Binary blob containing fastresume data
+    pub libtorrent_resume_data: Vec<u8>,
+    /// Binary blob containing metadata, missing for magnet links without metadata
+    pub metadata: Option<Vec<u8>>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_fixtures::{bencode, info, torrent_file, Version};
+
+    #[test]
+    fn derives_torrent_ids() {
+        let files = [("a.iso", 40000)];
+        for (version, v1, v2) in [
+            (Version::V1, true, false),
+            (Version::V2, false, true),
+            (Version::Hybrid, true, true),
+        ] {
+            let info = info("a.iso", 16384, &files, version);
+            let info_bytes = bencode(&info);
+            let sha1 = sha1_smol::Sha1::from(&info_bytes).digest().to_string();
+            let sha256 = hex(&Sha256::digest(&info_bytes));
+            let metadata: TorrentMetadata =
+                serde_bencode::from_bytes(&torrent_file(info, Vec::new())).unwrap();
+
+            assert_eq!(metadata.has_v1(), v1);
+            assert_eq!(metadata.has_v2(), v2);
+            assert_eq!(metadata.info_hash().unwrap(), sha1);
+            assert_eq!(metadata.info_hash_v2().unwrap(), sha256);
+            let expected = match v1 {
+                true => sha1,
+                false => sha256[..40].to_string(),
+            };
+            assert_eq!(metadata.torrent_id().unwrap(), expected);
+        }
+    }
+}
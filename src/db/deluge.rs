@@ -0,0 +1,476 @@
+//! Tools for reading and writing torrents in a Deluge config directory
+
+use crate::db::export::ExportedTorrent;
+use crate::db::import::{read_torrent_file, ImportedTorrent};
+use crate::path::{is_windows_style, mapped_file_paths};
+use serde_bytes::ByteBuf;
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// libtorrent fastresume data as saved by Deluge
+///
+/// Deluge has used several libtorrent versions, so every field that is not needed to import the
+/// torrent is optional, unlike in `FastresumeData`.
+#[derive(Deserialize, Debug)]
+struct DelugeFastresume {
+    /// Save path for torrent content
+    save_path: String,
+    /// Torrent pieces, one byte per piece with the lowest bit set if the piece has been downloaded
+    pieces: Option<ByteBuf>,
+    /// Renamed file paths relative to the save path
+    mapped_files: Option<Vec<String>>,
+    /// Trackers list for torrent
+    trackers: Option<Vec<Vec<String>>>,
+    /// Total uploaded amount for torrent
+    total_uploaded: Option<u64>,
+    /// Total downloaded amount for torrent
+    total_downloaded: Option<u64>,
+    /// Date torrent was added
+    added_time: Option<i64>,
+    /// Date torrent was completed
+    completed_time: Option<i64>,
+    /// Amount of time torrent has been active
+    active_time: Option<i64>,
+    /// Amount of time torrent has been seeding
+    seeding_time: Option<i64>,
+    /// Torrent pause state
+    paused: Option<i64>,
+}
+
+/// Deluge label plugin config, `label.conf`
+#[derive(Deserialize, Debug)]
+struct LabelConfig {
+    /// Label of each torrent, keyed by torrent hash
+    torrent_labels: BTreeMap<String, String>,
+}
+
+/// A Python value, for writing the pickled `torrents.state`
+#[derive(Debug)]
+enum Pickle {
+    /// `None`
+    None,
+    /// `True` or `False`
+    Bool(bool),
+    /// Integer
+    Int(i64),
+    /// Floating point number
+    Float(f64),
+    /// Unicode string
+    Str(String),
+    /// List
+    List(Vec<Pickle>),
+    /// Dictionary with string keys
+    Dict(Vec<(&'static str, Pickle)>),
+    /// Instance of a class, created without arguments and given the attributes
+    Object {
+        /// Module and name of the class
+        class: (&'static str, &'static str),
+        /// Instance attributes
+        attributes: Vec<(&'static str, Pickle)>,
+    },
+}
+
+impl Pickle {
+    /// Appends the value using pickle protocol 2 opcodes
+    fn write(&self, out: &mut Vec<u8>) {
+        match self {
+            Pickle::None => out.push(b'N'),
+            Pickle::Bool(true) => out.push(0x88),
+            Pickle::Bool(false) => out.push(0x89),
+            Pickle::Int(int) => match i32::try_from(*int) {
+                Ok(int) => {
+                    out.push(b'J');
+                    out.extend(int.to_le_bytes());
+                }
+                Err(_) => {
+                    out.extend([0x8a, 8]);
+                    out.extend(int.to_le_bytes());
+                }
+            },
+            Pickle::Float(float) => {
+                out.push(b'G');
+                out.extend(float.to_be_bytes());
+            }
+            Pickle::Str(string) => {
+                out.push(b'X');
+                out.extend((string.len() as u32).to_le_bytes());
+                out.extend(string.as_bytes());
+            }
+            Pickle::List(items) => {
+                out.push(b']');
+                if !items.is_empty() {
+                    out.push(b'(');
+                    for item in items {
+                        item.write(out);
+                    }
+                    out.push(b'e');
+                }
+            }
+            Pickle::Dict(items) => Pickle::write_dict(items, out),
+            Pickle::Object {
+                class: (module, name),
+                attributes,
+            } => {
+                out.push(b'c');
+                out.extend(format!("{module}\n{name}\n").as_bytes());
+                out.extend([b')', 0x81]);
+                Pickle::write_dict(attributes, out);
+                out.push(b'b');
+            }
+        }
+    }
+
+    /// Appends a dictionary with string keys
+    fn write_dict(items: &[(&str, Pickle)], out: &mut Vec<u8>) {
+        out.push(b'}');
+        if !items.is_empty() {
+            out.push(b'(');
+            for (key, value) in items {
+                Pickle::Str(key.to_string()).write(out);
+                value.write(out);
+            }
+            out.push(b'u');
+        }
+    }
+
+    /// Returns the value pickled with protocol 2
+    fn to_bytes(&self) -> Vec<u8> {
+        let mut out = vec![0x80, 2];
+        self.write(&mut out);
+        out.push(b'.');
+        out
+    }
+}
+
+/// Converts a qB file priority to the Deluge priority closest to it
+///
+/// qB uses 0 to skip a file, 1 for normal, 6 for high and 7 for maximum priority, while Deluge uses 0
+/// to skip a file, 1 for low, 4 for normal and 7 for high priority.
+fn deluge_file_priority(priority: u8) -> i64 {
+    match priority {
+        0 => 0,
+        1..=5 => 4,
+        _ => 7,
+    }
+}
+
+/// Returns the Deluge `TorrentState` of a torrent, as saved in `torrents.state`
+fn torrent_state(torrent: &ExportedTorrent) -> Pickle {
+    let fastresume = &torrent.fastresume;
+    let trackers = fastresume
+        .trackers
+        .iter()
+        .enumerate()
+        .flat_map(|(tier, urls)| {
+            urls.iter().map(move |url| {
+                Pickle::Dict(vec![
+                    ("url", Pickle::Str(url.clone())),
+                    ("tier", Pickle::Int(tier as i64)),
+                ])
+            })
+        })
+        .collect();
+    let limit = |limit: i64| match limit {
+        limit if limit > 0 => Pickle::Float(limit as f64 / 1024.0),
+        _ => Pickle::Float(-1.0),
+    };
+    Pickle::Object {
+        class: ("deluge.core.torrentmanager", "TorrentState"),
+        attributes: vec![
+            ("torrent_id", Pickle::Str(torrent.hash.clone())),
+            ("filename", Pickle::Str(format!("{}.torrent", torrent.hash))),
+            ("trackers", Pickle::List(trackers)),
+            ("storage_mode", Pickle::Str(String::from("sparse"))),
+            ("paused", Pickle::Bool(fastresume.paused != 0)),
+            ("save_path", Pickle::Str(fastresume.save_path.clone())),
+            ("max_connections", Pickle::Int(fastresume.max_connections)),
+            ("max_upload_slots", Pickle::Int(fastresume.max_uploads)),
+            ("max_upload_speed", limit(fastresume.upload_rate_limit)),
+            ("max_download_speed", limit(fastresume.download_rate_limit)),
+            ("prioritize_first_last", Pickle::Bool(false)),
+            (
+                "sequential_download",
+                Pickle::Bool(fastresume.sequential_download != 0),
+            ),
+            (
+                "file_priorities",
+                Pickle::List(
+                    (0..torrent.file_paths.len())
+                        .map(|index| {
+                            let priority = fastresume
+                                .file_priority
+                                .as_ref()
+                                .and_then(|priorities| priorities.get(index).copied())
+                                .unwrap_or(1);
+                            Pickle::Int(deluge_file_priority(priority))
+                        })
+                        .collect(),
+                ),
+            ),
+            ("queue", Pickle::Int(torrent.queue_position)),
+            ("auto_managed", Pickle::Bool(fastresume.auto_managed != 0)),
+            ("is_finished", Pickle::Bool(torrent.is_complete())),
+            ("stop_ratio", Pickle::Float(2.0)),
+            ("stop_at_ratio", Pickle::Bool(false)),
+            ("remove_at_ratio", Pickle::Bool(false)),
+            ("move_completed", Pickle::Bool(false)),
+            ("move_completed_path", Pickle::Str(String::new())),
+            ("magnet", Pickle::None),
+            ("owner", Pickle::Str(String::from("localclient"))),
+            ("shared", Pickle::Bool(false)),
+            ("super_seeding", Pickle::Bool(fastresume.super_seeding != 0)),
+            ("name", Pickle::Str(torrent.name.clone())),
+        ],
+    }
+}
+
+/// Reads the label of every torrent from `label.conf`, if the label plugin has been used
+///
+/// Deluge config files hold two JSON objects, a version header followed by the config itself.
+fn read_labels(label_file: &Path) -> Result<BTreeMap<String, String>, Box<dyn Error>> {
+    if !label_file.is_file() {
+        return Ok(BTreeMap::new());
+    }
+    let contents = fs::read_to_string(label_file)?;
+    let Some(config) = serde_json::Deserializer::from_str(&contents)
+        .into_iter::<serde_json::Value>()
+        .last()
+    else {
+        return Ok(BTreeMap::new());
+    };
+    let config: LabelConfig = serde_json::from_value(config?)?;
+    Ok(config.torrent_labels)
+}
+
+/// Reads a single torrent from its .torrent file and fastresume data
+fn read_torrent(torrent_file: &Path, fastresume: &[u8]) -> Result<ImportedTorrent, Box<dyn Error>> {
+    let (contents, metadata) = read_torrent_file(torrent_file)?;
+    let fastresume: DelugeFastresume = serde_bencode::from_bytes(fastresume)?;
+
+    let original_paths = metadata.file_paths();
+    let num_pieces = metadata.num_pieces();
+    let windows = is_windows_style(&fastresume.save_path);
+
+    let mut torrent = ImportedTorrent::new(contents, metadata, fastresume.save_path);
+    torrent.mapped_files = fastresume
+        .mapped_files
+        .map(|mapped_files| mapped_file_paths(Some(&mapped_files), &original_paths, windows))
+        .filter(|paths| *paths != original_paths);
+    if let Some(trackers) = fastresume.trackers.filter(|trackers| !trackers.is_empty()) {
+        torrent.trackers = trackers;
+    }
+    torrent.pieces = fastresume
+        .pieces
+        .filter(|pieces| pieces.len() == num_pieces)
+        .map(|pieces| pieces.iter().map(|piece| piece & 1 == 1).collect())
+        .unwrap_or_default();
+    torrent.uploaded = fastresume.total_uploaded.unwrap_or_default();
+    torrent.downloaded = fastresume.total_downloaded.unwrap_or_default();
+    torrent.added_time = fastresume.added_time.unwrap_or_default();
+    torrent.completed_time = fastresume.completed_time.unwrap_or_default();
+    torrent.active_time = fastresume.active_time.unwrap_or_default();
+    torrent.seeding_time = fastresume.seeding_time.unwrap_or_default();
+    torrent.paused = fastresume.paused.unwrap_or_default() != 0;
+
+    Ok(torrent)
+}
+
+/// Reads every torrent from a Deluge config directory
+///
+/// `state/torrents.fastresume` holds the libtorrent fastresume data of every torrent keyed by hash,
+/// which is paired with the `state/<hash>.torrent` file. The fastresume data already carries the
+/// state needed to import a torrent, so the pickled `state/torrents.state` is not read. Labels from
+/// the label plugin become categories. Torrents that cannot be read are reported and skipped.
+pub fn read_deluge(directory: &Path) -> Result<Vec<ImportedTorrent>, Box<dyn Error>> {
+    let state_directory = directory.join("state");
+    let fastresume_file = state_directory.join("torrents.fastresume");
+    if !fastresume_file.is_file() {
+        return Err(format!(
+            "{:?} does not contain state/torrents.fastresume",
+            directory.display()
+        )
+        .into());
+    }
+
+    let all_fastresume: BTreeMap<String, ByteBuf> =
+        serde_bencode::from_bytes(&fs::read(&fastresume_file)?)?;
+    let labels = read_labels(&directory.join("label.conf"))?;
+
+    let mut torrents = Vec::new();
+    for (hash, fastresume) in &all_fastresume {
+        let torrent_file = state_directory.join(format!("{hash}.torrent"));
+        if !torrent_file.is_file() {
+            println!("Import: skipping {}, no .torrent file", hash);
+            continue;
+        }
+        match read_torrent(&torrent_file, fastresume) {
+            Ok(mut torrent) => {
+                torrent.category = labels.get(hash).filter(|label| !label.is_empty()).cloned();
+                torrents.push(torrent);
+            }
+            Err(err) => println!("Import: skipping {}, {}", hash, err),
+        }
+    }
+
+    Ok(torrents)
+}
+
+/// Writes torrents to a Deluge config directory
+///
+/// Each torrent is written as `state/<hash>.torrent`, its fastresume data is added to
+/// `state/torrents.fastresume` as is, and its state to the pickled `state/torrents.state` Deluge
+/// needs to load it. Categories become labels in `label.conf`, tags are left out.
+pub fn write_deluge(directory: &Path, torrents: &[ExportedTorrent]) -> Result<(), Box<dyn Error>> {
+    let state_directory = directory.join("state");
+    fs::create_dir_all(&state_directory)?;
+
+    let mut all_fastresume = BTreeMap::new();
+    let mut labels = BTreeMap::new();
+    for torrent in torrents {
+        fs::write(
+            state_directory.join(format!("{}.torrent", torrent.hash)),
+            &torrent.torrent_file,
+        )?;
+        all_fastresume.insert(
+            torrent.hash.clone(),
+            ByteBuf::from(serde_bencode::to_bytes(&torrent.fastresume)?),
+        );
+        if let Some(category) = &torrent.category {
+            labels.insert(torrent.hash.clone(), category.to_lowercase());
+        }
+    }
+    fs::write(
+        state_directory.join("torrents.fastresume"),
+        serde_bencode::to_bytes(&all_fastresume)?,
+    )?;
+
+    let state = Pickle::Object {
+        class: ("deluge.core.torrentmanager", "TorrentManagerState"),
+        attributes: vec![(
+            "torrents",
+            Pickle::List(torrents.iter().map(torrent_state).collect()),
+        )],
+    };
+    fs::write(state_directory.join("torrents.state"), state.to_bytes())?;
+
+    if !labels.is_empty() {
+        // Deluge only accepts lowercase labels, each with its own options
+        let label_options = serde_json::json!({
+            "apply_max": false,
+            "max_download_speed": -1,
+            "max_upload_speed": -1,
+            "max_connections": -1,
+            "max_upload_slots": -1,
+            "prioritize_first_last": false,
+            "apply_queue": false,
+            "is_auto_managed": false,
+            "stop_at_ratio": false,
+            "stop_ratio": 2.0,
+            "remove_at_ratio": false,
+            "apply_move_completed": false,
+            "move_completed": false,
+            "move_completed_path": "",
+            "auto_add": false,
+            "auto_add_trackers": [],
+        });
+        let config = serde_json::json!({
+            "labels": labels
+                .values()
+                .map(|label| (label.clone(), label_options.clone()))
+                .collect::<serde_json::Map<_, _>>(),
+            "torrent_labels": labels,
+        });
+        let header = serde_json::json!({ "file": 1, "format": 1 });
+        fs::write(
+            directory.join("label.conf"),
+            format!(
+                "{}{}",
+                serde_json::to_string_pretty(&header)?,
+                serde_json::to_string_pretty(&config)?
+            ),
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::db_structs::TorrentMetadata;
+    use crate::db::test_fixtures::{bencode, bytes, dict, info, temp_dir, torrent_file, Version};
+    use serde_bencode::value::Value;
+
+    #[test]
+    fn reads_fastresume_list() {
+        let directory = temp_dir("deluge");
+        let state_directory = directory.join("state");
+        fs::create_dir(&state_directory).unwrap();
+
+        let files = [("disc1/a.iso", 32768), ("b.txt", 16384)];
+        let info = info("Pack", 16384, &files, Version::V1);
+        let contents = torrent_file(info, Vec::new());
+        let metadata: TorrentMetadata = serde_bencode::from_bytes(&contents).unwrap();
+        let hash = metadata.torrent_id().unwrap();
+        fs::write(state_directory.join(format!("{hash}.torrent")), &contents).unwrap();
+
+        let fastresume = dict(vec![
+            ("save_path", bytes("D:\\Downloads")),
+            ("pieces", Value::Bytes(vec![1, 0, 1])),
+            (
+                "mapped_files",
+                Value::List(vec![bytes("Pack\\disc1\\a.iso"), bytes("Pack\\c.txt")]),
+            ),
+            (
+                "trackers",
+                Value::List(vec![Value::List(vec![bytes("udp://tracker.example:1337")])]),
+            ),
+            ("total_uploaded", Value::Int(1000)),
+            ("paused", Value::Int(1)),
+        ]);
+        // Without a .torrent file the second torrent is skipped
+        let all_fastresume = dict(vec![
+            (hash.as_str(), Value::Bytes(bencode(&fastresume))),
+            (
+                "ffffffffffffffffffffffffffffffffffffffff",
+                Value::Bytes(bencode(&fastresume)),
+            ),
+        ]);
+        fs::write(
+            state_directory.join("torrents.fastresume"),
+            bencode(&all_fastresume),
+        )
+        .unwrap();
+        fs::write(
+            directory.join("label.conf"),
+            format!(
+                "{{\"file\": 1, \"format\": 1}}{{\"torrent_labels\": {{\"{hash}\": \"linux\"}}}}"
+            ),
+        )
+        .unwrap();
+
+        let torrents = read_deluge(&directory).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(torrents.len(), 1);
+        let torrent = &torrents[0];
+        assert_eq!(torrent.save_path, "D:\\Downloads");
+        assert_eq!(torrent.pieces, vec![true, false, true]);
+        assert_eq!(
+            torrent.mapped_files,
+            Some(vec![
+                String::from("Pack/disc1/a.iso"),
+                String::from("Pack/c.txt")
+            ])
+        );
+        assert_eq!(
+            torrent.trackers,
+            vec![vec![String::from("udp://tracker.example:1337")]]
+        );
+        assert_eq!(torrent.category.as_deref(), Some("linux"));
+        assert_eq!(torrent.uploaded, 1000);
+        assert!(torrent.paused);
+    }
+}
@@ -0,0 +1,308 @@
+//! Tools for comparing two torrents.db files
+
+use crate::db::filter::{fetch_torrents, Torrent, TorrentFilter};
+use rusqlite::{Connection, OpenFlags};
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Columns that are not compared directly, the row id differs between databases and the
+/// fastresume data is compared field by field
+const SKIPPED_COLUMNS: [&str; 3] = ["id", "torrent_id", "libtorrent_resume_data"];
+
+/// Diff information
+#[derive(Debug)]
+pub struct DiffRun {
+    /// Database to compare from, e.g. a backup
+    pub old: PathBuf,
+    /// Database to compare to, the configured database if not given
+    pub new: Option<PathBuf>,
+    /// Output the differences as JSON
+    pub json: bool,
+    /// File to write the differences to instead of printing them
+    pub output: Option<PathBuf>,
+}
+
+/// A changed field of a torrent in both databases
+struct FieldChange {
+    /// Column name, or the fastresume field name prefixed with `fastresume.`
+    field: String,
+    /// Value in the old database
+    old: Value,
+    /// Value in the new database
+    new: Value,
+}
+
+/// Opens a torrents.db file without write access
+fn open_read_only(path: &Path) -> Result<Connection, Box<dyn Error>> {
+    if !path.is_file() {
+        return Err(format!("{:?} does not exist", path.display()).into());
+    }
+    Ok(Connection::open_with_flags(
+        path,
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?)
+}
+
+/// Describes piece completion or priorities, stored as one character per piece
+fn describe_pieces(pieces: &str, label: &str) -> Value {
+    let set = pieces.bytes().filter(|piece| piece & 1 == 1).count();
+    Value::String(format!("{} of {} pieces {}", set, pieces.len(), label))
+}
+
+/// Returns a value in a readable form, binary fields are summarised
+fn readable_value(field: &str, value: Value) -> Value {
+    match (field, value) {
+        ("fastresume.pieces", Value::String(pieces)) => describe_pieces(&pieces, "downloaded"),
+        ("fastresume.piece_priority", Value::String(pieces)) => {
+            describe_pieces(&pieces, "prioritised")
+        }
+        ("fastresume.info-hash" | "fastresume.info-hash2", Value::Array(bytes)) => Value::String(
+            bytes
+                .iter()
+                .filter_map(Value::as_u64)
+                .map(|byte| format!("{:02x}", byte))
+                .collect(),
+        ),
+        ("metadata", Value::Array(bytes)) => Value::String(format!("{} bytes", bytes.len())),
+        (_, value) => value,
+    }
+}
+
+/// Renders a value for the human-readable report
+fn display_value(value: &Value) -> String {
+    match value {
+        Value::Null => String::from("none"),
+        Value::String(string) => format!("{:?}", string),
+        value => value.to_string(),
+    }
+}
+
+/// Adds the differing fields of two serialized structs to the changes
+fn compare_fields(
+    prefix: &str,
+    old: Map<String, Value>,
+    mut new: Map<String, Value>,
+    changes: &mut Vec<FieldChange>,
+) {
+    for (key, old_value) in old {
+        if prefix.is_empty() && SKIPPED_COLUMNS.contains(&key.as_str()) {
+            continue;
+        }
+        let new_value = new.remove(&key).unwrap_or(Value::Null);
+        if old_value == new_value {
+            continue;
+        }
+        let field = format!("{prefix}{key}");
+        changes.push(FieldChange {
+            old: readable_value(&field, old_value),
+            new: readable_value(&field, new_value),
+            field,
+        });
+    }
+}
+
+/// Returns the fields of a torrent that differ between the databases
+fn compare_torrents(old: &Torrent, new: &Torrent) -> Result<Vec<FieldChange>, Box<dyn Error>> {
+    let as_map = |value: Value| match value {
+        Value::Object(map) => map,
+        _ => Map::new(),
+    };
+
+    let mut changes = Vec::new();
+    compare_fields(
+        "",
+        as_map(serde_json::to_value(&old.data)?),
+        as_map(serde_json::to_value(&new.data)?),
+        &mut changes,
+    );
+    compare_fields(
+        "fastresume.",
+        as_map(serde_json::to_value(&old.fastresume)?),
+        as_map(serde_json::to_value(&new.fastresume)?),
+        &mut changes,
+    );
+    Ok(changes)
+}
+
+/// Compares two torrents.db files, reporting added and removed torrents and every changed field
+///
+/// Torrents are matched by their hash. Both the SQL columns and the decoded fastresume fields, such
+/// as the save path, trackers, transfer statistics and piece completion, are compared. Typically
+/// used to compare a backup made by qbfrt with the database, before restoring it or after a run.
+/// The global filters select which torrents are compared, a torrent is compared if it matches in
+/// either database, so a torrent moved out of a category shows as changed. Neither database is
+/// modified. JSON printed to stdout is the only output, so it can be piped to other tools. The
+/// database is only needed, and only opened by the caller, when `run.new` is not given.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::diff::{diff_databases, DiffRun};
+/// let run = DiffRun {
+///     old: PathBuf::from("/home/user/.local/share/qBittorrent/torrents.db-20240101120000.bak"),
+///     new: None,
+///     json: false,
+///     output: None,
+/// };
+/// diff_databases(Some(&connection), run, &TorrentFilter::default(), false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the number of torrents compared in each database,
+/// unless JSON is printed to stdout.
+pub fn diff_databases(
+    db: Option<&Connection>,
+    run: DiffRun,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    let quiet = run.json && run.output.is_none();
+    let old_db = open_read_only(&run.old)?;
+    let new_db = run.new.as_deref().map(open_read_only).transpose()?;
+    let Some(new_db) = new_db.as_ref().or(db) else {
+        return Err("no database to compare with".into());
+    };
+    let new_name = match &run.new {
+        Some(new) => format!("{:?}", new.display()),
+        None => String::from("the database"),
+    };
+    if !quiet {
+        println!("Diff: comparing {:?} with {}", run.old.display(), new_name);
+    }
+
+    // Torrents are matched before filtering, so a torrent that stopped matching is not removed
+    let old_torrents: BTreeMap<String, Torrent> =
+        fetch_torrents(&old_db, &TorrentFilter::default())?
+            .into_iter()
+            .map(|torrent| (torrent.data.torrent_id.clone(), torrent))
+            .collect();
+    let mut new_torrents: BTreeMap<String, Torrent> =
+        fetch_torrents(new_db, &TorrentFilter::default())?
+            .into_iter()
+            .map(|torrent| (torrent.data.torrent_id.clone(), torrent))
+            .collect();
+    if verbose && !quiet {
+        println!(
+            "Diff: {} torrents in the old database, {} in the new database",
+            old_torrents
+                .values()
+                .filter(|torrent| filter.matches(torrent))
+                .count(),
+            new_torrents
+                .values()
+                .filter(|torrent| filter.matches(torrent))
+                .count()
+        );
+    }
+
+    let mut removed = Vec::new();
+    let mut changed = Vec::new();
+    for (hash, old) in &old_torrents {
+        match new_torrents.remove(hash) {
+            None if filter.matches(old) => removed.push(old),
+            None => {}
+            Some(new) if filter.matches(old) || filter.matches(&new) => {
+                let changes = compare_torrents(old, &new)?;
+                if !changes.is_empty() {
+                    changed.push((new.display_name().to_string(), hash, changes));
+                }
+            }
+            Some(_) => {}
+        }
+    }
+    let added: Vec<&Torrent> = new_torrents
+        .values()
+        .filter(|torrent| filter.matches(torrent))
+        .collect();
+
+    let report = match run.json {
+        true => {
+            let summary = |torrent: &&Torrent| json!({"hash": torrent.data.torrent_id, "name": torrent.display_name()});
+            let document = json!({
+                "old": run.old.display().to_string(),
+                "new": run.new.as_ref().map(|new| new.display().to_string()),
+                "added": added.iter().map(summary).collect::<Vec<_>>(),
+                "removed": removed.iter().map(summary).collect::<Vec<_>>(),
+                "changed": changed
+                    .iter()
+                    .map(|(name, hash, changes)| {
+                        json!({
+                            "hash": hash,
+                            "name": name,
+                            "changes": changes
+                                .iter()
+                                .map(|change| json!({
+                                    "field": change.field,
+                                    "old": change.old,
+                                    "new": change.new,
+                                }))
+                                .collect::<Vec<_>>(),
+                        })
+                    })
+                    .collect::<Vec<_>>(),
+            });
+            serde_json::to_string_pretty(&document)? + "\n"
+        }
+        false => {
+            let mut lines = Vec::new();
+            for torrent in &added {
+                lines.push(format!(
+                    "+ {}: {}",
+                    torrent.data.torrent_id,
+                    torrent.display_name()
+                ));
+            }
+            for torrent in &removed {
+                lines.push(format!(
+                    "- {}: {}",
+                    torrent.data.torrent_id,
+                    torrent.display_name()
+                ));
+            }
+            for (name, hash, changes) in &changed {
+                lines.push(format!("~ {}: {}", hash, name));
+                for change in changes {
+                    lines.push(format!(
+                        "    {}: {} -> {}",
+                        change.field,
+                        display_value(&change.old),
+                        display_value(&change.new)
+                    ));
+                }
+            }
+            lines.iter().map(|line| format!("{line}\n")).collect()
+        }
+    };
+
+    match &run.output {
+        Some(output) => {
+            fs::write(output, &report)?;
+            println!("Diff: differences written to {:?}", output.display());
+        }
+        None => print!("{}", report),
+    }
+    if quiet {
+        return Ok(());
+    }
+
+    for (count, action) in [
+        (added.len(), "added"),
+        (removed.len(), "removed"),
+        (changed.len(), "changed"),
+    ] {
+        match count {
+            0 => {}
+            1 => println!("Diff: 1 torrent was {}", action),
+            _ => println!("Diff: {} torrents were {}", count, action),
+        }
+    }
+    if added.is_empty() && removed.is_empty() && changed.is_empty() {
+        println!("Diff: no differences were found");
+    }
+
+    Ok(())
+}
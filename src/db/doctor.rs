@@ -0,0 +1,168 @@
+//! Tools for checking the health of the torrents.db database
+
+use crate::db::db_structs::{FastresumeData, FetchedHealthData, TorrentMetadata};
+use crate::db::save_path::save_paths_match;
+use rusqlite::Connection;
+use serde_rusqlite::from_rows;
+use std::collections::BTreeMap;
+use std::error::Error;
+
+/// Runs a series of read-only checks against the database and reports any problems found
+///
+/// The following checks are performed:
+/// - SQLite `PRAGMA integrity_check`
+/// - Every `libtorrent_resume_data` blob decodes as fastresume data
+/// - Every `metadata` blob decodes as a torrent
+/// - The `torrent_id` matches the info hash in both the fastresume data and the metadata
+/// - Queue positions of downloading torrents are unique and contiguous
+/// - The `target_save_path` (or `download_path` for incomplete torrents) agrees with the fastresume
+///   `save_path`
+///
+/// Returns the number of problems found.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::doctor::check_database;
+/// let problems = check_database(&connection, false)?;
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output each torrent hash as it is checked.
+pub fn check_database(db: &Connection, verbose: bool) -> Result<usize, Box<dyn Error>> {
+    println!("Doctor: checking database");
+
+    let mut problems = 0;
+
+    let mut integrity_stmt = db.prepare("PRAGMA integrity_check")?;
+    let integrity_rows = integrity_stmt.query_map([], |row| row.get::<usize, String>(0))?;
+    for row in integrity_rows {
+        let message = row?;
+        if message != "ok" {
+            println!("Doctor: integrity check failed: {}", message);
+            problems += 1;
+        }
+    }
+
+    let mut search_stmt = db.prepare(
+        "SELECT id, torrent_id, queue_position, target_save_path, download_path, libtorrent_resume_data, metadata
+        FROM torrents",
+    )?;
+    let all_torrents = from_rows::<FetchedHealthData>(search_stmt.query([])?);
+
+    // Queue positions are collected by position so duplicates and gaps can be found afterwards
+    let mut queue_positions: BTreeMap<i64, Vec<String>> = BTreeMap::new();
+    for row in all_torrents {
+        let torrent = row?;
+
+        if verbose {
+            println!("Doctor: checking {}", torrent.torrent_id);
+        }
+
+        match torrent.queue_position {
+            -1 => {}
+            position if position < -1 => {
+                println!(
+                    "{}: invalid queue_position {}",
+                    torrent.torrent_id, torrent.queue_position
+                );
+                problems += 1;
+            }
+            position => queue_positions
+                .entry(position)
+                .or_default()
+                .push(torrent.torrent_id.clone()),
+        }
+
+        let bencoded_data = torrent.libtorrent_resume_data.as_slice();
+        match serde_bencode::from_bytes::<FastresumeData>(bencoded_data) {
+            Ok(libtorrent_resume_data) => {
+                let info_hash = libtorrent_resume_data.torrent_id();
+                if info_hash != torrent.torrent_id {
+                    println!(
+                        "{}: torrent_id does not match fastresume info hash {}",
+                        torrent.torrent_id, info_hash
+                    );
+                    problems += 1;
+                }
+
+                // Incomplete torrents are saved to their download path until they finish
+                let downloading_to_download_path = torrent
+                    .download_path
+                    .is_some_and(|path| save_paths_match(&path, &libtorrent_resume_data.save_path));
+
+                // An empty target_save_path means the torrent uses automatic torrent management
+                if let Some(target_save_path) = torrent.target_save_path.filter(|p| !p.is_empty()) {
+                    if !downloading_to_download_path
+                        && !save_paths_match(&target_save_path, &libtorrent_resume_data.save_path)
+                    {
+                        println!(
+                            "{}: target_save_path {} does not match fastresume save_path {}",
+                            torrent.torrent_id, target_save_path, libtorrent_resume_data.save_path
+                        );
+                        problems += 1;
+                    }
+                }
+            }
+            Err(err) => {
+                println!(
+                    "{}: libtorrent_resume_data could not be decoded: {}",
+                    torrent.torrent_id, err
+                );
+                problems += 1;
+            }
+        }
+
+        // Magnet links that have not received metadata yet have no metadata blob
+        if let Some(metadata) = torrent.metadata {
+            match serde_bencode::from_bytes::<TorrentMetadata>(&metadata)
+                .and_then(|metadata| metadata.torrent_id())
+            {
+                Ok(info_hash) => {
+                    if info_hash != torrent.torrent_id {
+                        println!(
+                            "{}: torrent_id does not match metadata info hash {}",
+                            torrent.torrent_id, info_hash
+                        );
+                        problems += 1;
+                    }
+                }
+                Err(err) => {
+                    println!(
+                        "{}: metadata could not be decoded: {}",
+                        torrent.torrent_id, err
+                    );
+                    problems += 1;
+                }
+            }
+        }
+    }
+
+    let mut expected_position = 0;
+    for (position, torrent_ids) in queue_positions {
+        if position != expected_position {
+            println!(
+                "Doctor: queue positions {} to {} are missing",
+                expected_position,
+                position - 1
+            );
+            problems += 1;
+        }
+        if torrent_ids.len() > 1 {
+            println!(
+                "Doctor: queue position {} is shared by {}",
+                position,
+                torrent_ids.join(", ")
+            );
+            problems += 1;
+        }
+        expected_position = position + 1;
+    }
+
+    match problems {
+        0 => println!("Doctor: no problems were found"),
+        1 => println!("Doctor: 1 problem was found"),
+        _ => println!("Doctor: {} problems were found", problems),
+    }
+
+    Ok(problems)
+}
@@ -0,0 +1,211 @@
+//! Tools for exporting torrents from torrents.db to other clients
+
+use crate::db::db_structs::{replace_announce, FastresumeData, TorrentMetadata};
+use crate::db::deluge::write_deluge;
+use crate::db::filter::{fetch_torrents, TorrentFilter};
+use crate::db::migrate::Migration;
+use crate::db::rtorrent::write_rtorrent;
+use crate::db::transmission::write_transmission;
+use crate::path::{is_windows_style, mapped_file_paths, QbPath};
+use rusqlite::Connection;
+use std::error::Error;
+use std::fs;
+use std::path::PathBuf;
+
+/// Client format to export torrents to
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ExportFormat {
+    /// Transmission config directory with `torrents` and `resume` directories
+    Transmission,
+    /// Deluge config directory with a `state` directory
+    Deluge,
+    /// rTorrent session directory
+    Rtorrent,
+}
+
+impl ExportFormat {
+    /// Returns the name of the client
+    fn client(&self) -> &'static str {
+        match self {
+            ExportFormat::Transmission => "Transmission",
+            ExportFormat::Deluge => "Deluge",
+            ExportFormat::Rtorrent => "rTorrent",
+        }
+    }
+}
+
+/// Export information
+#[derive(Debug)]
+pub struct ExportRun {
+    /// Client format to write
+    pub format: ExportFormat,
+    /// Directory to write to, which must not exist or be empty
+    pub directory: PathBuf,
+    /// Path mappings applied to exported save paths, and the separator to write them with
+    pub migration: Migration,
+}
+
+/// A torrent from torrents.db, prepared for writing in another client's format
+#[derive(Debug)]
+pub struct ExportedTorrent {
+    /// Torrent hash id
+    pub hash: String,
+    /// Name qB displays for the torrent
+    pub name: String,
+    /// Torrent metadata
+    pub metadata: TorrentMetadata,
+    /// The stored metadata with the trackers of the fastresume data as announce URLs, written as the
+    /// .torrent file
+    pub torrent_file: Vec<u8>,
+    /// Fastresume data, with the save path mapped
+    pub fastresume: FastresumeData,
+    /// Path of every file relative to the save path using '/' separators
+    pub file_paths: Vec<String>,
+    /// Torrent category
+    pub category: Option<String>,
+    /// Torrent tags
+    pub tags: Vec<String>,
+    /// Queue position, -1 for seeding torrents
+    pub queue_position: i64,
+    /// Whether each piece has been downloaded
+    pub pieces: Vec<bool>,
+}
+
+impl ExportedTorrent {
+    /// Returns true if every piece has been downloaded
+    pub fn is_complete(&self) -> bool {
+        !self.pieces.is_empty() && self.pieces.iter().all(|have| *have)
+    }
+}
+
+/// Packs piece completion into a bitfield with the most significant bit first
+pub fn pack_bitfield(pieces: &[bool]) -> Vec<u8> {
+    pieces
+        .chunks(8)
+        .map(|chunk| {
+            chunk
+                .iter()
+                .enumerate()
+                .filter(|(_, have)| **have)
+                .fold(0, |byte, (index, _)| byte | (0x80 >> index))
+        })
+        .collect()
+}
+
+/// Exports the torrents matching the filter to another client's format
+///
+/// Every torrent with metadata is written with its .torrent file, save path, piece completion,
+/// transfer statistics and dates, so the other client can pick up where qB left off. Path mappings
+/// are applied to the exported save paths. The database is not modified.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::export::{export_torrents, ExportFormat, ExportRun};
+/// use qbfrt::db::migrate::Migration;
+/// use qbfrt::path::Separator;
+/// let run = ExportRun {
+///     format: ExportFormat::Transmission,
+///     directory: PathBuf::from("/tmp/transmission"),
+///     migration: Migration {
+///         mappings: Vec::new(),
+///         separator: Separator::Unix,
+///     },
+/// };
+/// export_torrents(&connection, run, &TorrentFilter::default(), false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the torrent hash, name and save path of every exported
+/// torrent.
+pub fn export_torrents(
+    db: &Connection,
+    run: ExportRun,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    println!(
+        "Export: exporting torrents to {} in {:?}",
+        run.format.client(),
+        run.directory.display()
+    );
+
+    if run.directory.exists() && fs::read_dir(&run.directory)?.next().is_some() {
+        return Err(format!("{:?} is not empty", run.directory.display()).into());
+    }
+
+    let torrents = fetch_torrents(db, filter)?;
+
+    let mut exported = Vec::new();
+    for torrent in torrents {
+        let name = torrent.display_name().to_string();
+        let hash = torrent.data.torrent_id.clone();
+        let tags = torrent.tags();
+        let Some((raw_metadata, Ok(metadata))) = torrent.data.metadata.map(|raw_metadata| {
+            let metadata = serde_bencode::from_bytes::<TorrentMetadata>(&raw_metadata);
+            (raw_metadata, metadata)
+        }) else {
+            println!("Export: skipping {} ({}), no metadata", name, hash);
+            continue;
+        };
+
+        let mut fastresume = torrent.fastresume;
+        // Mapped files use the separators of the OS qB runs on, not those of the mapped save path
+        let windows = is_windows_style(&fastresume.save_path);
+        let file_paths = mapped_file_paths(
+            fastresume.mapped_files.as_deref(),
+            &metadata.file_paths(),
+            windows,
+        );
+
+        let save_path = QbPath::parse(&fastresume.save_path);
+        fastresume.save_path = run
+            .migration
+            .map(&save_path)
+            .unwrap_or(save_path)
+            .to_native(run.migration.separator);
+
+        let torrent_file =
+            replace_announce(&raw_metadata, &fastresume.trackers)?.unwrap_or(raw_metadata);
+
+        let pieces = fastresume
+            .pieces
+            .bytes()
+            .map(|piece| piece & 1 == 1)
+            .collect();
+
+        if verbose {
+            println!("{}: {}, {}", hash, name, fastresume.save_path);
+        }
+
+        exported.push(ExportedTorrent {
+            hash,
+            name,
+            metadata,
+            torrent_file,
+            fastresume,
+            file_paths,
+            category: torrent
+                .data
+                .category
+                .filter(|category| !category.is_empty()),
+            tags,
+            queue_position: torrent.data.queue_position,
+            pieces,
+        });
+    }
+
+    fs::create_dir_all(&run.directory)?;
+    match run.format {
+        ExportFormat::Transmission => write_transmission(&run.directory, &exported)?,
+        ExportFormat::Deluge => write_deluge(&run.directory, &exported)?,
+        ExportFormat::Rtorrent => write_rtorrent(&run.directory, &exported)?,
+    }
+
+    match exported.len() {
+        0 => println!("Export: no torrents were exported"),
+        1 => println!("Export: 1 torrent was exported"),
+        count => println!("Export: {} torrents were exported", count),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,216 @@
+//! Tools for selecting a subset of torrents
+
+use crate::db::db_structs::{DatabaseData, FastresumeData, TorrentMetadata};
+use crate::db::tracker_url::{host_matches, tracker_host};
+use crate::path::QbPath;
+use rusqlite::Connection;
+use serde_rusqlite::from_rows;
+use std::error::Error;
+
+/// Torrent selection criteria
+///
+/// Every criterion that is set must match for a torrent to be selected. An empty filter selects
+/// every torrent.
+#[derive(Debug, Default)]
+pub struct TorrentFilter {
+    /// Torrent hashes or hash prefixes, any of which may match
+    pub hashes: Vec<String>,
+    /// Exact category name, an empty string selects torrents without a category
+    pub category: Option<String>,
+    /// Tag the torrent must have
+    pub tag: Option<String>,
+    /// String contained in any of the torrent's tracker URLs
+    pub tracker: Option<String>,
+    /// Domain of any of the torrent's trackers, subdomains included
+    pub tracker_host: Option<String>,
+    /// Whether the torrent must be private or public
+    pub private: Option<bool>,
+    /// Path the torrent must be saved in or below
+    pub save_path: Option<QbPath>,
+    /// Case-insensitive string contained in the torrent name
+    pub name: Option<String>,
+}
+
+/// A torrent row together with its decoded fastresume data
+#[derive(Debug)]
+pub struct Torrent {
+    /// The torrent's database columns
+    pub data: DatabaseData,
+    /// The decoded `libtorrent_resume_data` column
+    pub fastresume: FastresumeData,
+}
+
+impl Torrent {
+    /// Returns the name qB displays for the torrent, preferring the renamed name if there is one
+    pub fn display_name(&self) -> &str {
+        self.data
+            .name
+            .as_deref()
+            .filter(|name| !name.is_empty())
+            .unwrap_or(&self.fastresume.name)
+    }
+
+    /// Returns true if the torrent's metadata marks it as private
+    ///
+    /// Torrents without metadata are treated as public.
+    pub fn is_private(&self) -> bool {
+        self.data
+            .metadata
+            .as_deref()
+            .and_then(|metadata| serde_bencode::from_bytes::<TorrentMetadata>(metadata).ok())
+            .is_some_and(|metadata| metadata.is_private())
+    }
+
+    /// Returns the total size of the torrent's files, torrents without metadata count as empty
+    pub fn total_size(&self) -> i64 {
+        self.data
+            .metadata
+            .as_deref()
+            .and_then(|metadata| serde_bencode::from_bytes::<TorrentMetadata>(metadata).ok())
+            .map_or(0, |metadata| metadata.total_size())
+    }
+
+    /// Returns the torrent's tags, split from the comma-separated `tags` column
+    pub fn tags(&self) -> Vec<String> {
+        split_tags(self.data.tags.as_deref().unwrap_or_default())
+    }
+}
+
+/// Splits a comma-separated tag string, trimming whitespace and dropping empty tags
+pub fn split_tags(tags: &str) -> Vec<String> {
+    tags.split(',')
+        .map(str::trim)
+        .filter(|tag| !tag.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+impl TorrentFilter {
+    /// Returns true if no criteria are set
+    pub fn is_empty(&self) -> bool {
+        self.hashes.is_empty()
+            && self.category.is_none()
+            && self.tag.is_none()
+            && self.tracker.is_none()
+            && self.tracker_host.is_none()
+            && self.private.is_none()
+            && self.save_path.is_none()
+            && self.name.is_none()
+    }
+
+    /// Returns true if the torrent matches every criterion that is set
+    pub fn matches(&self, torrent: &Torrent) -> bool {
+        if !self.hashes.is_empty()
+            && !self.hashes.iter().any(|hash| {
+                torrent
+                    .data
+                    .torrent_id
+                    .to_lowercase()
+                    .starts_with(&hash.to_lowercase())
+            })
+        {
+            return false;
+        }
+
+        if let Some(category) = &self.category {
+            if torrent.data.category.as_deref().unwrap_or_default() != category {
+                return false;
+            }
+        }
+
+        if let Some(tag) = &self.tag {
+            if !torrent.tags().contains(tag) {
+                return false;
+            }
+        }
+
+        if let Some(tracker) = &self.tracker {
+            if !torrent
+                .fastresume
+                .trackers
+                .iter()
+                .flatten()
+                .any(|url| url.contains(tracker))
+            {
+                return false;
+            }
+        }
+
+        if let Some(domain) = &self.tracker_host {
+            if !torrent
+                .fastresume
+                .trackers
+                .iter()
+                .flatten()
+                .filter_map(|url| tracker_host(url))
+                .any(|host| host_matches(&host, domain))
+            {
+                return false;
+            }
+        }
+
+        if let Some(private) = self.private {
+            if torrent.is_private() != private {
+                return false;
+            }
+        }
+
+        if let Some(save_path) = &self.save_path {
+            let target_matches = torrent
+                .data
+                .target_save_path
+                .as_deref()
+                .is_some_and(|path| QbPath::parse_portable(path).starts_with(save_path));
+            if !target_matches
+                && !QbPath::parse(&torrent.fastresume.save_path).starts_with(save_path)
+            {
+                return false;
+            }
+        }
+
+        if let Some(name) = &self.name {
+            if !torrent
+                .display_name()
+                .to_lowercase()
+                .contains(&name.to_lowercase())
+            {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+/// Fetches every torrent matching the filter, decoding its fastresume data
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::filter::{fetch_torrents, TorrentFilter};
+/// let filter = TorrentFilter {
+///     category: Some(String::from("movies")),
+///     ..Default::default()
+/// };
+/// let torrents = fetch_torrents(&connection, &filter)?;
+/// ```
+pub fn fetch_torrents(
+    db: &Connection,
+    filter: &TorrentFilter,
+) -> Result<Vec<Torrent>, Box<dyn Error>> {
+    let mut search_stmt = db.prepare("SELECT * FROM torrents ORDER BY id")?;
+    let all_torrents = from_rows::<DatabaseData>(search_stmt.query([])?);
+
+    let mut torrents = Vec::new();
+    for row in all_torrents {
+        let data = row?;
+        let fastresume: FastresumeData =
+            serde_bencode::from_bytes(data.libtorrent_resume_data.as_slice())?;
+        let torrent = Torrent { data, fastresume };
+
+        if filter.matches(&torrent) {
+            torrents.push(torrent);
+        }
+    }
+
+    Ok(torrents)
+}
@@ -0,0 +1,589 @@
+//! Tools for importing torrents from other clients into torrents.db
+
+use crate::db::db_structs::{FastresumeData, TorrentMetadata};
+use crate::db::deluge::read_deluge;
+use crate::db::migrate::Migration;
+use crate::db::rtorrent::read_rtorrent;
+use crate::db::tag::join_tags;
+use crate::db::transmission::read_transmission;
+use crate::path::QbPath;
+use rusqlite::{named_params, Connection, OptionalExtension};
+use serde_bencode::value::Value;
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// Top-level .torrent keys qB keeps in the stored metadata, besides the info dictionary
+///
+/// qB stores the metadata libtorrent generates from the loaded torrent, which keeps these keys and
+/// drops any other keys a client or torrent creator added.
+const METADATA_KEYS: [&[u8]; 9] = [
+    b"announce",
+    b"announce-list",
+    b"comment",
+    b"created by",
+    b"creation date",
+    b"httpseeds",
+    b"nodes",
+    b"piece layers",
+    b"url-list",
+];
+
+/// Client to import torrents from
+#[derive(Debug)]
+pub enum ImportSource {
+    /// Transmission config directory containing the `torrents` and `resume` directories
+    Transmission(PathBuf),
+    /// Deluge config directory containing the `state` directory
+    Deluge(PathBuf),
+    /// rTorrent session directory
+    Rtorrent(PathBuf),
+}
+
+impl ImportSource {
+    /// Returns the name of the client
+    fn client(&self) -> &'static str {
+        match self {
+            ImportSource::Transmission(_) => "Transmission",
+            ImportSource::Deluge(_) => "Deluge",
+            ImportSource::Rtorrent(_) => "rTorrent",
+        }
+    }
+
+    /// Returns the directory torrents are read from
+    fn directory(&self) -> &Path {
+        match self {
+            ImportSource::Transmission(directory)
+            | ImportSource::Deluge(directory)
+            | ImportSource::Rtorrent(directory) => directory,
+        }
+    }
+}
+
+/// Import information
+#[derive(Debug)]
+pub struct ImportRun {
+    /// Client and directory to import from
+    pub source: ImportSource,
+    /// Path mappings applied to imported save paths, and the separator for the fastresume save_path
+    pub migration: Migration,
+    /// Category for imported torrents the client did not assign one to
+    pub category: Option<String>,
+    /// Report what would be imported without modifying the database
+    pub dry_run: bool,
+}
+
+/// A torrent read from another client, ready to be added to torrents.db
+#[derive(Debug)]
+pub struct ImportedTorrent {
+    /// The client's .torrent file, the metadata stored in the database is derived from it
+    pub torrent_file: Vec<u8>,
+    /// Metadata from the client's .torrent file
+    pub metadata: TorrentMetadata,
+    /// Path the client saved the content in
+    pub save_path: String,
+    /// Display name, if the torrent was renamed
+    pub name: Option<String>,
+    /// Path of every file relative to the save path using '/' separators, if any were renamed
+    pub mapped_files: Option<Vec<String>>,
+    /// Category or label
+    pub category: Option<String>,
+    /// Tags or labels
+    pub tags: Vec<String>,
+    /// Tracker tiers
+    pub trackers: Vec<Vec<String>>,
+    /// Web seed URLs (BEP 19)
+    pub url_list: Vec<String>,
+    /// HTTP seed URLs (BEP 17)
+    pub httpseeds: Vec<String>,
+    /// Whether each piece has been downloaded, empty if unknown so qB checks the data
+    pub pieces: Vec<bool>,
+    /// Total uploaded in bytes
+    pub uploaded: u64,
+    /// Total downloaded in bytes
+    pub downloaded: u64,
+    /// Date the torrent was added, 0 if unknown
+    pub added_time: i64,
+    /// Date the torrent was completed, 0 if unknown or incomplete
+    pub completed_time: i64,
+    /// Active time in seconds
+    pub active_time: i64,
+    /// Seeding time in seconds
+    pub seeding_time: i64,
+    /// Torrent is stopped
+    pub paused: bool,
+}
+
+impl ImportedTorrent {
+    /// Creates an imported torrent with the trackers and web seeds of its metadata and no transfer
+    /// statistics
+    ///
+    /// qB reads trackers and web seeds from the fastresume data, so they are copied from the metadata.
+    pub fn new(
+        torrent_file: Vec<u8>,
+        metadata: TorrentMetadata,
+        save_path: String,
+    ) -> ImportedTorrent {
+        let trackers = match (&metadata.announce_list, &metadata.announce) {
+            (Some(announce_list), _) if !announce_list.is_empty() => announce_list.clone(),
+            (_, Some(announce)) => vec![vec![announce.clone()]],
+            _ => Vec::new(),
+        };
+        let url_list = web_seeds(&torrent_file, b"url-list");
+        let httpseeds = web_seeds(&torrent_file, b"httpseeds");
+        ImportedTorrent {
+            torrent_file,
+            metadata,
+            save_path,
+            name: None,
+            mapped_files: None,
+            category: None,
+            tags: Vec::new(),
+            trackers,
+            url_list,
+            httpseeds,
+            pieces: Vec::new(),
+            uploaded: 0,
+            downloaded: 0,
+            added_time: 0,
+            completed_time: 0,
+            active_time: 0,
+            seeding_time: 0,
+            paused: false,
+        }
+    }
+
+    /// Returns true if every piece has been downloaded
+    pub fn is_complete(&self) -> bool {
+        !self.pieces.is_empty() && self.pieces.iter().all(|have| *have)
+    }
+}
+
+/// Reads a .torrent file, returning its contents and metadata
+pub fn read_torrent_file(path: &Path) -> Result<(Vec<u8>, TorrentMetadata), Box<dyn Error>> {
+    let torrent_file = fs::read(path)?;
+    let metadata = serde_bencode::from_bytes(&torrent_file)?;
+    Ok((torrent_file, metadata))
+}
+
+/// Returns the metadata qB would store for a .torrent file, its info dictionary and the
+/// [METADATA_KEYS]
+fn qb_metadata(torrent_file: &[u8]) -> Result<Vec<u8>, serde_bencode::Error> {
+    let Value::Dict(mut dict) = serde_bencode::from_bytes::<Value>(torrent_file)? else {
+        return Err(serde_bencode::Error::Custom(String::from(
+            "metadata is not a dictionary",
+        )));
+    };
+    dict.retain(|key, _| key == b"info" || METADATA_KEYS.contains(&key.as_slice()));
+    serde_bencode::to_bytes(&Value::Dict(dict))
+}
+
+/// Returns the URLs of a web seed key of a .torrent file, which may be a single URL or a list
+fn web_seeds(torrent_file: &[u8], key: &[u8]) -> Vec<String> {
+    let Ok(Value::Dict(dict)) = serde_bencode::from_bytes::<Value>(torrent_file) else {
+        return Vec::new();
+    };
+    let urls = match dict.get(key) {
+        Some(Value::Bytes(url)) => vec![url],
+        Some(Value::List(urls)) => urls
+            .iter()
+            .filter_map(|url| match url {
+                Value::Bytes(url) => Some(url),
+                _ => None,
+            })
+            .collect(),
+        _ => Vec::new(),
+    };
+    urls.into_iter()
+        .filter_map(|url| String::from_utf8(url.clone()).ok())
+        .filter(|url| !url.is_empty())
+        .collect()
+}
+
+/// Unpacks a bitfield with the most significant bit first, as used by BitTorrent clients
+pub fn unpack_bitfield(bitfield: &[u8], count: usize) -> Vec<bool> {
+    (0..count)
+        .map(|index| {
+            bitfield
+                .get(index / 8)
+                .is_some_and(|byte| byte & (0x80 >> (index % 8)) != 0)
+        })
+        .collect()
+}
+
+/// Decodes a hex-encoded info hash into its bytes
+fn hash_bytes(hash: &str) -> Vec<u8> {
+    (0..hash.len() / 2)
+        .filter_map(|index| u8::from_str_radix(&hash[index * 2..index * 2 + 2], 16).ok())
+        .collect()
+}
+
+/// Imports torrents from another client, adding a row to torrents.db for every new torrent
+///
+/// The fastresume data is built from the client's save path, piece completion, transfer statistics
+/// and dates, so qB can start seeding without rechecking the data. Torrents already in the database
+/// are skipped. Path mappings are applied to the imported save paths, so torrents can be imported
+/// from another machine. Complete torrents are added as seeding, incomplete torrents are queued after
+/// the existing queue. The metadata is stored the way qB stores it, the info dictionary with the
+/// trackers, web seeds, piece layers and creation details of the .torrent file, so other keys added
+/// by a client are dropped. v2 and hybrid torrents are added under the same id qB uses. All torrents are added in a single transaction.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::import::{import_torrents, ImportRun, ImportSource};
+/// use qbfrt::db::migrate::Migration;
+/// use qbfrt::path::Separator;
+/// let run = ImportRun {
+///     source: ImportSource::Transmission(PathBuf::from("/home/user/.config/transmission")),
+///     migration: Migration {
+///         mappings: Vec::new(),
+///         separator: Separator::Unix,
+///     },
+///     category: Some(String::from("transmission")),
+///     dry_run: false,
+/// };
+/// import_torrents(&connection, run, false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the torrent hash, name, save path and completion
+/// of every imported torrent.
+pub fn import_torrents(
+    db: &Connection,
+    run: ImportRun,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    match run.dry_run {
+        true => println!("Import: dry run, the database will not be modified"),
+        false => println!(
+            "Import: importing torrents from {} in {:?}",
+            run.source.client(),
+            run.source.directory().display()
+        ),
+    }
+
+    let torrents = match &run.source {
+        ImportSource::Transmission(directory) => read_transmission(directory)?,
+        ImportSource::Deluge(directory) => read_deluge(directory)?,
+        ImportSource::Rtorrent(directory) => read_rtorrent(directory)?,
+    };
+
+    let transaction = db.unchecked_transaction()?;
+    let mut next_position: i64 = transaction.query_row(
+        "SELECT COALESCE(MAX(queue_position), -1) + 1 FROM torrents",
+        [],
+        |row| row.get(0),
+    )?;
+    let now = chrono::offset::Utc::now().timestamp();
+
+    let mut seen = BTreeSet::new();
+    let mut num_imported = 0;
+    let mut num_skipped = 0;
+    for torrent in torrents {
+        let info_hash = torrent.metadata.torrent_id()?;
+        let name = torrent
+            .name
+            .clone()
+            .unwrap_or_else(|| torrent.metadata.name());
+
+        let existing = transaction
+            .query_row(
+                "SELECT id FROM torrents WHERE torrent_id = :hash",
+                named_params! { ":hash": info_hash },
+                |row| row.get::<usize, u64>(0),
+            )
+            .optional()?;
+        if existing.is_some() || !seen.insert(info_hash.clone()) {
+            println!(
+                "Import: skipping {} ({}), already in the database",
+                name, info_hash
+            );
+            num_skipped += 1;
+            continue;
+        }
+
+        let save_path = QbPath::parse(&torrent.save_path);
+        let save_path = run.migration.map(&save_path).unwrap_or(save_path);
+        let native_save_path = save_path.to_native(run.migration.separator);
+        let complete = torrent.is_complete();
+        let queue_position = match complete {
+            true => -1,
+            false => {
+                next_position += 1;
+                next_position - 1
+            }
+        };
+
+        if verbose {
+            let completion = match torrent.pieces.is_empty() {
+                true => String::from("completion unknown, qB will check the data"),
+                false => format!(
+                    "{} of {} pieces",
+                    torrent.pieces.iter().filter(|have| **have).count(),
+                    torrent.pieces.len()
+                ),
+            };
+            println!(
+                "{}: {}, {}, {}",
+                info_hash, name, native_save_path, completion
+            );
+        }
+
+        if run.dry_run {
+            num_imported += 1;
+            continue;
+        }
+
+        let completed_time = match (complete, torrent.completed_time) {
+            (false, _) => 0,
+            (true, 0) => now,
+            (true, completed_time) => completed_time,
+        };
+        // libtorrent stores mapped paths with the separator of the platform it runs on
+        let mapped_files = torrent.mapped_files.map(|paths| {
+            paths
+                .iter()
+                .map(|path| path.replace('/', &run.migration.separator.as_char().to_string()))
+                .collect()
+        });
+        let libtorrent_resume_data = FastresumeData {
+            active_time: torrent.active_time,
+            added_time: match torrent.added_time {
+                0 => now,
+                added_time => added_time,
+            },
+            allocation: String::from("sparse"),
+            apply_ip_filter: 1,
+            auto_managed: !torrent.paused as i64,
+            completed_time,
+            disable_dht: 0,
+            disable_lsd: 0,
+            disable_pex: 0,
+            download_rate_limit: -1,
+            file_format: String::from("libtorrent resume file"),
+            file_version: 1,
+            file_priority: Some(vec![1; torrent.metadata.file_paths().len()]),
+            finished_time: match complete {
+                true => torrent.seeding_time,
+                false => 0,
+            },
+            httpseeds: torrent.httpseeds,
+            // libtorrent writes all-zero hashes for the versions a torrent does not have
+            info_hash: match torrent.metadata.has_v1() {
+                true => hash_bytes(&torrent.metadata.info_hash()?),
+                false => vec![0; 20],
+            },
+            info_hash2: match torrent.metadata.has_v2() {
+                true => hash_bytes(&torrent.metadata.info_hash_v2()?),
+                false => vec![0; 32],
+            },
+            last_download: 0,
+            last_seen_complete: completed_time,
+            last_upload: 0,
+            // The data was not written by libtorrent, which does not read the version back
+            libtorrent_version: String::new(),
+            mapped_files,
+            max_connections: -1,
+            max_uploads: -1,
+            name: torrent.metadata.name(),
+            num_complete: 0,
+            num_downloaded: 0,
+            num_incomplete: 0,
+            paused: torrent.paused as u64,
+            piece_priority: None,
+            pieces: torrent
+                .pieces
+                .iter()
+                .map(|have| match have {
+                    true => '\u{1}',
+                    false => '\u{0}',
+                })
+                .collect(),
+            save_path: native_save_path,
+            seed_mode: 0,
+            seeding_time: torrent.seeding_time.max(0) as u64,
+            sequential_download: 0,
+            share_mode: 0,
+            stop_when_ready: 0,
+            super_seeding: 0,
+            total_downloaded: torrent.downloaded,
+            total_uploaded: torrent.uploaded,
+            trackers: torrent.trackers,
+            upload_mode: 0,
+            upload_rate_limit: -1,
+            url_list: torrent.url_list,
+        };
+
+        transaction.execute(
+            "INSERT INTO torrents (torrent_id, queue_position, name, category, tags, target_save_path,
+                download_path, content_layout, ratio_limit, seeding_time_limit,
+                inactive_seeding_time_limit, has_outer_pieces_priority, has_seed_status,
+                operating_mode, stopped, stop_condition, libtorrent_resume_data, metadata)
+            VALUES (:hash, :position, :name, :category, :tags, :tsp, NULL, 'Original', -2000, -2, -2,
+                0, :seed, 'AutoManaged', :stopped, 'None', :lrd, :metadata);",
+            named_params! {
+                ":hash": info_hash,
+                ":position": queue_position,
+                ":name": torrent.name,
+                ":category": torrent.category.or(run.category.clone()),
+                ":tags": join_tags(torrent.tags),
+                ":tsp": save_path.to_portable(),
+                ":seed": complete as i64,
+                ":stopped": torrent.paused as i64,
+                ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?,
+                ":metadata": qb_metadata(&torrent.torrent_file)?,
+            },
+        )?;
+
+        if verbose {
+            println!("Import: imported {}", info_hash);
+        }
+
+        num_imported += 1;
+    }
+
+    if !run.dry_run {
+        transaction.commit()?;
+    }
+
+    match num_skipped {
+        0 => {}
+        1 => println!("Import: 1 torrent was skipped"),
+        _ => println!("Import: {} torrents were skipped", num_skipped),
+    }
+    match (num_imported, run.dry_run) {
+        (0, _) => println!("Import: no torrents need importing"),
+        (1, true) => println!("Import: 1 torrent would be imported"),
+        (_, true) => println!("Import: {} torrents would be imported", num_imported),
+        (1, false) => println!("Import: 1 torrent was imported"),
+        (_, false) => println!("Import: {} torrents were imported", num_imported),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::filter::{fetch_torrents, TorrentFilter};
+    use crate::db::migrate::PathMapping;
+    use crate::db::test_fixtures::{
+        bencode, bytes, database, dict, info, temp_dir, torrent_file, Version,
+    };
+    use crate::path::Separator;
+
+    #[test]
+    fn unpacks_bitfields() {
+        assert_eq!(unpack_bitfield(&[0b1010_0000], 3), vec![true, false, true]);
+        assert_eq!(unpack_bitfield(&[0xff, 0b1000_0000], 9), vec![true; 9]);
+        // Missing bytes count as missing pieces
+        assert_eq!(unpack_bitfield(&[0xff], 10)[8..], [false, false]);
+    }
+
+    #[test]
+    fn keeps_qb_metadata_keys() {
+        let info = info("a.iso", 16384, &[("a.iso", 16384)], Version::V1);
+        let contents = torrent_file(
+            info.clone(),
+            vec![
+                ("comment", bytes("fixture")),
+                ("url-list", bytes("https://seed.example/")),
+                ("publisher", bytes("someone")),
+                ("rtorrent", dict(Vec::new())),
+            ],
+        );
+        let Value::Dict(metadata) =
+            serde_bencode::from_bytes::<Value>(&qb_metadata(&contents).unwrap()).unwrap()
+        else {
+            panic!("metadata is not a dictionary");
+        };
+        let mut keys: Vec<_> = metadata.keys().map(|key| key.as_slice()).collect();
+        keys.sort();
+        assert_eq!(
+            keys,
+            [b"announce".as_slice(), b"comment", b"info", b"url-list"]
+        );
+        assert_eq!(metadata.get(b"info".as_slice()), Some(&info));
+    }
+
+    #[test]
+    fn imports_torrents_into_database() {
+        let directory = temp_dir("import");
+        fs::create_dir(directory.join("torrents")).unwrap();
+        fs::create_dir(directory.join("resume")).unwrap();
+        let torrents = [
+            // Complete, so added as seeding
+            (
+                "complete",
+                info(
+                    "Pack",
+                    16384,
+                    &[("a.iso", 32768), ("b.txt", 100)],
+                    Version::V1,
+                ),
+                Some(dict(vec![("blocks", bytes("all"))])),
+            ),
+            // Incomplete, queued first
+            (
+                "hybrid",
+                info("b.iso", 16384, &[("b.iso", 40000)], Version::Hybrid),
+                Some(dict(vec![("bitfield", Value::Bytes(vec![0b1000_0000]))])),
+            ),
+            // Completion unknown, queued second
+            (
+                "v2",
+                info("c.iso", 16384, &[("c.iso", 40000)], Version::V2),
+                None,
+            ),
+        ];
+        let mut ids = Vec::new();
+        for (name, info, progress) in torrents {
+            let contents = torrent_file(info, Vec::new());
+            let metadata: TorrentMetadata = serde_bencode::from_bytes(&contents).unwrap();
+            ids.push(metadata.torrent_id().unwrap());
+            fs::write(directory.join(format!("torrents/{name}.torrent")), contents).unwrap();
+            let mut resume = vec![("destination", bytes("/old/data"))];
+            resume.extend(progress.map(|progress| ("progress", progress)));
+            fs::write(
+                directory.join(format!("resume/{name}.resume")),
+                bencode(&dict(resume)),
+            )
+            .unwrap();
+        }
+
+        let db = database();
+        let run = ImportRun {
+            source: ImportSource::Transmission(directory.clone()),
+            migration: Migration {
+                mappings: vec![PathMapping {
+                    from: QbPath::parse("/old"),
+                    to: QbPath::parse("/srv"),
+                }],
+                separator: Separator::Unix,
+            },
+            category: Some(String::from("imported")),
+            dry_run: false,
+        };
+        import_torrents(&db, run, false).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+
+        let torrents = fetch_torrents(&db, &TorrentFilter::default()).unwrap();
+        assert_eq!(torrents.len(), 3);
+        let expected = [(-1, "\u{1}\u{1}\u{1}"), (0, "\u{1}\u{0}\u{0}"), (1, "")];
+        for ((torrent, id), (queue_position, pieces)) in torrents.iter().zip(&ids).zip(expected) {
+            assert_eq!(&torrent.data.torrent_id, id);
+            assert_eq!(&torrent.fastresume.torrent_id(), id);
+            assert_eq!(torrent.data.queue_position, queue_position);
+            assert_eq!(torrent.data.has_seed_status, (queue_position < 0) as i64);
+            assert_eq!(torrent.data.target_save_path.as_deref(), Some("/srv/data"));
+            assert_eq!(torrent.fastresume.save_path, "/srv/data");
+            assert_eq!(torrent.fastresume.pieces, pieces);
+            assert_eq!(torrent.data.category.as_deref(), Some("imported"));
+            assert_eq!(
+                torrent.fastresume.trackers,
+                vec![vec![String::from("http://tracker.example/announce")]]
+            );
+        }
+    }
+}
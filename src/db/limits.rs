@@ -0,0 +1,308 @@
+//! Tools for modifying per-torrent seeding, rate and connection limits
+
+use crate::db::filter::{fetch_torrents, TorrentFilter};
+use rusqlite::{named_params, Connection};
+use std::error::Error;
+
+/// qB value for limits that follow the global setting
+pub const USE_GLOBAL_LIMIT: i64 = -2;
+/// qB value for limits that are disabled
+pub const NO_LIMIT: i64 = -1;
+/// libtorrent value for unlimited rates and connection counts
+pub const UNLIMITED: i64 = -1;
+/// Largest ratio limit qB accepts
+pub const MAX_RATIO: f64 = 9998.0;
+/// Largest seeding time limit qB accepts, in minutes
+pub const MAX_SEEDING_TIME: i64 = 525600;
+
+/// Limits to set on torrents, unset fields are left unchanged
+#[derive(Debug, Default)]
+pub struct TorrentLimits {
+    /// Ratio limit, stored by qB as the ratio multiplied by 1000
+    pub ratio_limit: Option<i64>,
+    /// Seeding time limit in minutes
+    pub seeding_time_limit: Option<i64>,
+    /// Inactive seeding time limit in minutes
+    pub inactive_seeding_time_limit: Option<i64>,
+    /// Upload rate limit in bytes per second
+    pub upload_rate_limit: Option<i64>,
+    /// Download rate limit in bytes per second
+    pub download_rate_limit: Option<i64>,
+    /// Maximum number of connections
+    pub max_connections: Option<i64>,
+    /// Maximum number of upload slots
+    pub max_uploads: Option<i64>,
+}
+
+impl TorrentLimits {
+    /// Returns true if no limits are set
+    pub fn is_empty(&self) -> bool {
+        self.ratio_limit.is_none()
+            && self.seeding_time_limit.is_none()
+            && self.inactive_seeding_time_limit.is_none()
+            && self.upload_rate_limit.is_none()
+            && self.download_rate_limit.is_none()
+            && self.max_connections.is_none()
+            && self.max_uploads.is_none()
+    }
+}
+
+/// Parses a ratio limit such as `2.0`, `global` or `unlimited` into qB's stored form
+pub fn parse_ratio_limit(value: &str) -> Result<i64, String> {
+    match value {
+        "global" => Ok(USE_GLOBAL_LIMIT * 1000),
+        "unlimited" => Ok(NO_LIMIT * 1000),
+        _ => match value.parse::<f64>() {
+            Ok(ratio) if (0.0..=MAX_RATIO).contains(&ratio) => Ok((ratio * 1000.0).round() as i64),
+            _ => Err(format!(
+                "ratio limit {value} must be between 0 and {MAX_RATIO}, 'global' or 'unlimited'"
+            )),
+        },
+    }
+}
+
+/// Parses a time limit such as `90`, `12h`, `30d`, `global` or `unlimited` into minutes
+///
+/// Plain numbers are minutes, and `m`, `h`, `d` and `w` suffixes are accepted.
+pub fn parse_time_limit(value: &str) -> Result<i64, String> {
+    let error = || {
+        format!(
+            "time limit {value} must be up to {MAX_SEEDING_TIME} minutes (e.g. 90, 12h, 30d), 'global' or 'unlimited'"
+        )
+    };
+    match value {
+        "global" => Ok(USE_GLOBAL_LIMIT),
+        "unlimited" => Ok(NO_LIMIT),
+        _ => {
+            let (number, multiplier) = match value.char_indices().last() {
+                Some((index, 'm')) => (&value[..index], 1),
+                Some((index, 'h')) => (&value[..index], 60),
+                Some((index, 'd')) => (&value[..index], 60 * 24),
+                Some((index, 'w')) => (&value[..index], 60 * 24 * 7),
+                _ => (value, 1),
+            };
+            match number
+                .parse::<i64>()
+                .ok()
+                .and_then(|n| n.checked_mul(multiplier))
+            {
+                Some(minutes) if (0..=MAX_SEEDING_TIME).contains(&minutes) => Ok(minutes),
+                _ => Err(error()),
+            }
+        }
+    }
+}
+
+/// Parses a rate limit such as `500K`, `2M` or `unlimited` into bytes per second
+///
+/// Plain numbers are bytes per second, and `K`, `M` and `G` suffixes are powers of 1024. A rate of
+/// zero in any unit is unlimited, as in qB. qB stores rates as 32-bit integers, so larger rates are
+/// rejected.
+pub fn parse_rate_limit(value: &str) -> Result<i64, String> {
+    let error = || {
+        format!(
+            "rate limit {value} must be a positive rate up to {} bytes per second (e.g. 500K, 2M) or 'unlimited'",
+            i32::MAX
+        )
+    };
+    match value {
+        "unlimited" => Ok(UNLIMITED),
+        _ => {
+            let (number, multiplier) = match value.char_indices().last() {
+                Some((index, 'K' | 'k')) => (&value[..index], 1024),
+                Some((index, 'M' | 'm')) => (&value[..index], 1024 * 1024),
+                Some((index, 'G' | 'g')) => (&value[..index], 1024 * 1024 * 1024),
+                _ => (value, 1),
+            };
+            match number.parse::<i64>() {
+                Ok(0) => Ok(UNLIMITED),
+                Ok(rate) if rate > 0 => rate
+                    .checked_mul(multiplier)
+                    .filter(|rate| *rate <= i32::MAX as i64)
+                    .ok_or_else(error),
+                _ => Err(error()),
+            }
+        }
+    }
+}
+
+/// Parses a connection or upload slot count, or `unlimited`
+pub fn parse_count_limit(value: &str) -> Result<i64, String> {
+    match value {
+        "unlimited" => Ok(UNLIMITED),
+        _ => match value.parse::<i64>() {
+            Ok(count) if count > 0 && count <= i32::MAX as i64 => Ok(count),
+            _ => Err(format!(
+                "limit {value} must be a positive number up to {} or 'unlimited'",
+                i32::MAX
+            )),
+        },
+    }
+}
+
+/// Returns a readable description of a qB seeding limit
+fn describe_limit(value: i64) -> String {
+    match value {
+        USE_GLOBAL_LIMIT => String::from("global"),
+        NO_LIMIT => String::from("unlimited"),
+        _ => value.to_string(),
+    }
+}
+
+/// Sets seeding, rate and connection limits on every torrent matching the filter
+///
+/// Seeding limits are stored in the `ratio_limit`, `seeding_time_limit` and
+/// `inactive_seeding_time_limit` columns, where `-2` follows the global setting and `-1` disables the
+/// limit. Rate and connection limits are stored in `libtorrent_resume_data`, where `-1` is unlimited.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::limits::{change_limits, parse_ratio_limit, parse_time_limit, TorrentLimits};
+/// let limits = TorrentLimits {
+///     ratio_limit: Some(parse_ratio_limit("2.0")?),
+///     seeding_time_limit: Some(parse_time_limit("30d")?),
+///     ..Default::default()
+/// };
+/// change_limits(&connection, limits, &filter, false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the torrent hash and new limits for every updated
+/// torrent.
+pub fn change_limits(
+    db: &Connection,
+    limits: TorrentLimits,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    println!("Limits: updating torrent limits");
+
+    let torrents = fetch_torrents(db, filter)?;
+
+    let mut num_updated = 0;
+    for torrent in torrents {
+        let mut data = torrent.data;
+        let mut libtorrent_resume_data = torrent.fastresume;
+
+        let before = (
+            data.ratio_limit,
+            data.seeding_time_limit,
+            data.inactive_seeding_time_limit,
+            libtorrent_resume_data.upload_rate_limit,
+            libtorrent_resume_data.download_rate_limit,
+            libtorrent_resume_data.max_connections,
+            libtorrent_resume_data.max_uploads,
+        );
+
+        data.ratio_limit = limits.ratio_limit.unwrap_or(data.ratio_limit);
+        data.seeding_time_limit = limits.seeding_time_limit.unwrap_or(data.seeding_time_limit);
+        data.inactive_seeding_time_limit = limits
+            .inactive_seeding_time_limit
+            .unwrap_or(data.inactive_seeding_time_limit);
+        libtorrent_resume_data.upload_rate_limit = limits
+            .upload_rate_limit
+            .unwrap_or(libtorrent_resume_data.upload_rate_limit);
+        libtorrent_resume_data.download_rate_limit = limits
+            .download_rate_limit
+            .unwrap_or(libtorrent_resume_data.download_rate_limit);
+        libtorrent_resume_data.max_connections = limits
+            .max_connections
+            .unwrap_or(libtorrent_resume_data.max_connections);
+        libtorrent_resume_data.max_uploads = limits
+            .max_uploads
+            .unwrap_or(libtorrent_resume_data.max_uploads);
+
+        let after = (
+            data.ratio_limit,
+            data.seeding_time_limit,
+            data.inactive_seeding_time_limit,
+            libtorrent_resume_data.upload_rate_limit,
+            libtorrent_resume_data.download_rate_limit,
+            libtorrent_resume_data.max_connections,
+            libtorrent_resume_data.max_uploads,
+        );
+        if before == after {
+            continue;
+        }
+
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET ratio_limit = :rl, seeding_time_limit = :stl, inactive_seeding_time_limit = :istl,
+                    libtorrent_resume_data = :lrd
+                WHERE id = :id
+                RETURNING torrent_id;",
+        )?;
+        update_stmt.query_row(
+            named_params! {
+                ":rl": data.ratio_limit,
+                ":stl": data.seeding_time_limit,
+                ":istl": data.inactive_seeding_time_limit,
+                ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?,
+                ":id": data.id,
+            },
+            |row| {
+                let updated_row_id = row.get::<usize, String>(0)?;
+
+                if verbose {
+                    let ratio_limit = match data.ratio_limit {
+                        limit if limit < 0 => describe_limit(limit / 1000),
+                        limit => format!("{:.2}", limit as f64 / 1000.0),
+                    };
+                    println!("Limits: updated limits for {}", updated_row_id);
+                    println!(
+                        "{}: ratio {}, seeding time {}, inactive seeding time {}",
+                        updated_row_id,
+                        ratio_limit,
+                        describe_limit(data.seeding_time_limit),
+                        describe_limit(data.inactive_seeding_time_limit)
+                    );
+                    println!(
+                        "{}: upload rate {}, download rate {}, connections {}, upload slots {}",
+                        updated_row_id,
+                        libtorrent_resume_data.upload_rate_limit,
+                        libtorrent_resume_data.download_rate_limit,
+                        libtorrent_resume_data.max_connections,
+                        libtorrent_resume_data.max_uploads
+                    );
+                }
+
+                num_updated += 1;
+
+                Ok(())
+            },
+        )?;
+    }
+
+    match num_updated {
+        0 => println!("Limits: no torrents were updated"),
+        1 => println!("Limits: 1 torrent was updated"),
+        _ => println!("Limits: {} torrents were updated", num_updated),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_rate_limits() {
+        let cases = [
+            ("unlimited", Ok(UNLIMITED)),
+            ("0", Ok(UNLIMITED)),
+            ("0K", Ok(UNLIMITED)),
+            ("0M", Ok(UNLIMITED)),
+            ("500", Ok(500)),
+            ("500K", Ok(500 * 1024)),
+            ("2m", Ok(2 * 1024 * 1024)),
+            ("1G", Ok(1024 * 1024 * 1024)),
+        ];
+        for (value, expected) in cases {
+            assert_eq!(parse_rate_limit(value), expected, "{value}");
+        }
+        for value in ["-1", "2G", "1.5M", "K", "fast", "9223372036854775807K"] {
+            assert!(parse_rate_limit(value).is_err(), "{value}");
+        }
+    }
+}
@@ -0,0 +1,405 @@
+//! Tools for merging the torrents of another torrents.db into the database
+
+use crate::db::db_structs::FastresumeData;
+use crate::db::filter::{fetch_torrents, Torrent, TorrentFilter};
+use crate::db::migrate::Migration;
+use crate::db::tag::join_tags;
+use crate::path::QbPath;
+use rusqlite::{named_params, Connection, OpenFlags};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::path::PathBuf;
+
+/// How to resolve a torrent that is in both databases
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ConflictMode {
+    /// Keep the torrent already in the target database
+    KeepTarget,
+    /// Replace the target torrent with the source torrent
+    KeepSource,
+    /// Keep whichever torrent was added most recently, the target wins ties
+    Newer,
+    /// Keep the target torrent, adding the trackers and tags of the source torrent
+    Merge,
+}
+
+impl ConflictMode {
+    /// Parses a conflict mode from its command line name
+    pub fn parse(mode: &str) -> Result<ConflictMode, String> {
+        match mode {
+            "keep-target" => Ok(ConflictMode::KeepTarget),
+            "keep-source" => Ok(ConflictMode::KeepSource),
+            "newer" => Ok(ConflictMode::Newer),
+            "merge" => Ok(ConflictMode::Merge),
+            other => Err(format!(
+                "unknown conflict mode {other}, expected 'keep-target', 'keep-source', 'newer' or 'merge'"
+            )),
+        }
+    }
+}
+
+/// Merge information
+#[derive(Debug)]
+pub struct MergeRun {
+    /// Path to the torrents.db to merge from
+    pub source: PathBuf,
+    /// How to resolve torrents that are in both databases
+    pub conflict: ConflictMode,
+    /// Path mappings applied to incoming save paths and download paths
+    pub migration: Migration,
+    /// Report what would be merged without modifying the database
+    pub dry_run: bool,
+}
+
+/// What happens to a source torrent
+enum MergeAction {
+    /// Add the torrent as a new row
+    Insert,
+    /// Overwrite the target row
+    Replace,
+    /// Add the trackers and tags to the target row
+    Combine,
+    /// Leave the target row alone
+    Skip,
+}
+
+/// Applies the path mappings to the save path and download path of an incoming torrent
+///
+/// Returns true if any path was changed.
+fn remap_paths(torrent: &mut Torrent, migration: &Migration) -> bool {
+    let mut changed = false;
+    // qB always stores "target_save_path" and "download_path" with Unix-style separators
+    for path in [
+        &mut torrent.data.target_save_path,
+        &mut torrent.data.download_path,
+    ] {
+        if let Some(mapped) = path
+            .as_deref()
+            .filter(|path| !path.is_empty())
+            .and_then(|path| migration.map(&QbPath::parse_portable(path)))
+        {
+            *path = Some(mapped.to_portable());
+            changed = true;
+        }
+    }
+    if let Some(mapped) = migration.map(&QbPath::parse(&torrent.fastresume.save_path)) {
+        torrent.fastresume.save_path = mapped.to_native(migration.separator);
+        changed = true;
+    }
+    changed
+}
+
+/// Adds the trackers of the source to the target, keeping the tier each tracker was found in
+///
+/// Returns the number of trackers added.
+fn merge_trackers(target: &mut Vec<Vec<String>>, source: &[Vec<String>]) -> usize {
+    let mut added = 0;
+    for (tier, trackers) in source.iter().enumerate() {
+        for tracker in trackers {
+            if target.iter().flatten().any(|existing| existing == tracker) {
+                continue;
+            }
+            while target.len() <= tier {
+                target.push(Vec::new());
+            }
+            target[tier].push(tracker.clone());
+            added += 1;
+        }
+    }
+    added
+}
+
+/// Merges the torrents of another torrents.db into the database, keyed by torrent hash
+///
+/// Torrents only in the source are added, and torrents in both databases are resolved with the
+/// conflict mode. Path mappings are applied to the save paths and download paths of incoming
+/// torrents, so a database from another machine can be merged. The download queue is renumbered so
+/// the queued torrents of the target keep their order, followed by the newly queued torrents of the
+/// source in their order. The source database is only read, and the target is updated in a single
+/// transaction.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::merge::{merge_databases, ConflictMode, MergeRun};
+/// use qbfrt::db::migrate::Migration;
+/// use qbfrt::path::Separator;
+/// let run = MergeRun {
+///     source: PathBuf::from("/mnt/seedbox2/qBittorrent/torrents.db"),
+///     conflict: ConflictMode::Newer,
+///     migration: Migration {
+///         mappings: Vec::new(),
+///         separator: Separator::Unix,
+///     },
+///     dry_run: false,
+/// };
+/// merge_databases(&connection, run, &TorrentFilter::default(), false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the torrent hash, name and outcome of every source
+/// torrent, and the old and new queue position of every renumbered torrent.
+pub fn merge_databases(
+    db: &Connection,
+    run: MergeRun,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    match run.dry_run {
+        true => println!("Merge: dry run, the database will not be modified"),
+        false => println!("Merge: merging torrents from {:?}", run.source.display()),
+    }
+
+    if !run.source.is_file() {
+        return Err(format!("{:?} does not exist", run.source.display()).into());
+    }
+    let source_db = Connection::open_with_flags(
+        &run.source,
+        OpenFlags::SQLITE_OPEN_READ_ONLY
+            | OpenFlags::SQLITE_OPEN_URI
+            | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+    )?;
+    let mut incoming = fetch_torrents(&source_db, filter)?;
+    // Queued torrents are merged in queue order so they keep it in the target
+    incoming.sort_by_key(|torrent| {
+        (
+            torrent.data.queue_position < 0,
+            torrent.data.queue_position,
+            torrent.data.id,
+        )
+    });
+
+    let existing: BTreeMap<String, Torrent> = fetch_torrents(db, &TorrentFilter::default())?
+        .into_iter()
+        .map(|torrent| (torrent.data.torrent_id.clone(), torrent))
+        .collect();
+
+    // The queue as hashes with their position before the merge, the target's queued torrents first
+    let mut queued: Vec<&Torrent> = existing
+        .values()
+        .filter(|torrent| torrent.data.queue_position >= 0)
+        .collect();
+    queued.sort_by_key(|torrent| (torrent.data.queue_position, torrent.data.id));
+    let mut queue: Vec<(String, i64)> = queued
+        .iter()
+        .map(|torrent| (torrent.data.torrent_id.clone(), torrent.data.queue_position))
+        .collect();
+
+    let transaction = db.unchecked_transaction()?;
+    let mut num_inserted = 0;
+    let mut num_replaced = 0;
+    let mut num_combined = 0;
+    let mut num_skipped = 0;
+    let mut num_remapped = 0;
+    for mut torrent in incoming {
+        let hash = torrent.data.torrent_id.clone();
+        let name = torrent.display_name().to_string();
+        let target = existing.get(&hash);
+
+        let action = match (target, run.conflict) {
+            (None, _) => MergeAction::Insert,
+            (Some(_), ConflictMode::KeepTarget) => MergeAction::Skip,
+            (Some(_), ConflictMode::KeepSource) => MergeAction::Replace,
+            (Some(target), ConflictMode::Newer) => {
+                match torrent.fastresume.added_time > target.fastresume.added_time {
+                    true => MergeAction::Replace,
+                    false => MergeAction::Skip,
+                }
+            }
+            (Some(_), ConflictMode::Merge) => MergeAction::Combine,
+        };
+
+        match action {
+            MergeAction::Skip => {
+                if verbose {
+                    println!("{}: {}, keeping the target torrent", hash, name);
+                }
+                num_skipped += 1;
+            }
+            MergeAction::Combine => {
+                let Some(target) = target else { continue };
+                let mut fastresume: FastresumeData =
+                    serde_bencode::from_bytes(target.data.libtorrent_resume_data.as_slice())?;
+                let added_trackers =
+                    merge_trackers(&mut fastresume.trackers, &torrent.fastresume.trackers);
+                let mut tags = target.tags();
+                let added_tags: Vec<String> = torrent
+                    .tags()
+                    .into_iter()
+                    .filter(|tag| !tags.contains(tag))
+                    .collect();
+                tags.extend(added_tags.iter().cloned());
+
+                if added_trackers == 0 && added_tags.is_empty() {
+                    if verbose {
+                        println!("{}: {}, nothing to merge", hash, name);
+                    }
+                    num_skipped += 1;
+                    continue;
+                }
+                if verbose {
+                    println!(
+                        "{}: {}, adding {} trackers and {} tags to the target torrent",
+                        hash,
+                        name,
+                        added_trackers,
+                        added_tags.len()
+                    );
+                }
+                num_combined += 1;
+                if run.dry_run {
+                    continue;
+                }
+
+                transaction.execute(
+                    "UPDATE torrents SET tags = :tags, libtorrent_resume_data = :lrd WHERE id = :id",
+                    named_params! {
+                        ":tags": match added_tags.is_empty() {
+                            true => target.data.tags.clone(),
+                            false => join_tags(tags),
+                        },
+                        ":lrd": serde_bencode::to_bytes(&fastresume)?,
+                        ":id": target.data.id,
+                    },
+                )?;
+            }
+            MergeAction::Insert | MergeAction::Replace => {
+                if remap_paths(&mut torrent, &run.migration) {
+                    num_remapped += 1;
+                }
+
+                // Queue positions are placeholders until the queue is renumbered below
+                let in_queue = queue.iter().position(|(queued, _)| *queued == hash);
+                match (torrent.data.queue_position >= 0, in_queue) {
+                    (true, None) => queue.push((hash.clone(), torrent.data.queue_position)),
+                    (false, Some(index)) => {
+                        queue.remove(index);
+                    }
+                    _ => {}
+                }
+                let queue_position = match (torrent.data.queue_position >= 0, target) {
+                    (false, _) => -1,
+                    (true, Some(target)) if target.data.queue_position >= 0 => {
+                        target.data.queue_position
+                    }
+                    (true, _) => torrent.data.queue_position,
+                };
+
+                if verbose {
+                    let outcome = match action {
+                        MergeAction::Replace => "replacing the target torrent",
+                        _ => "adding",
+                    };
+                    println!(
+                        "{}: {}, {}, {}",
+                        hash, name, outcome, torrent.fastresume.save_path
+                    );
+                }
+                match action {
+                    MergeAction::Replace => num_replaced += 1,
+                    _ => num_inserted += 1,
+                }
+                if run.dry_run {
+                    continue;
+                }
+
+                let data = &torrent.data;
+                let params = named_params! {
+                    ":hash": hash,
+                    ":position": queue_position,
+                    ":name": data.name,
+                    ":category": data.category,
+                    ":tags": data.tags,
+                    ":tsp": data.target_save_path,
+                    ":dp": data.download_path,
+                    ":layout": data.content_layout,
+                    ":ratio": data.ratio_limit,
+                    ":seeding": data.seeding_time_limit,
+                    ":inactive": data.inactive_seeding_time_limit,
+                    ":outer": data.has_outer_pieces_priority,
+                    ":seed": data.has_seed_status,
+                    ":mode": data.operating_mode,
+                    ":stopped": data.stopped,
+                    ":condition": data.stop_condition,
+                    ":lrd": serde_bencode::to_bytes(&torrent.fastresume)?,
+                    ":metadata": data.metadata,
+                };
+                match action {
+                    MergeAction::Replace => transaction.execute(
+                        "UPDATE torrents SET queue_position = :position, name = :name,
+                            category = :category, tags = :tags, target_save_path = :tsp,
+                            download_path = :dp, content_layout = :layout, ratio_limit = :ratio,
+                            seeding_time_limit = :seeding, inactive_seeding_time_limit = :inactive,
+                            has_outer_pieces_priority = :outer, has_seed_status = :seed,
+                            operating_mode = :mode, stopped = :stopped, stop_condition = :condition,
+                            libtorrent_resume_data = :lrd, metadata = :metadata
+                        WHERE torrent_id = :hash",
+                        params,
+                    )?,
+                    _ => transaction.execute(
+                        "INSERT INTO torrents (torrent_id, queue_position, name, category, tags,
+                            target_save_path, download_path, content_layout, ratio_limit,
+                            seeding_time_limit, inactive_seeding_time_limit,
+                            has_outer_pieces_priority, has_seed_status, operating_mode, stopped,
+                            stop_condition, libtorrent_resume_data, metadata)
+                        VALUES (:hash, :position, :name, :category, :tags, :tsp, :dp, :layout,
+                            :ratio, :seeding, :inactive, :outer, :seed, :mode, :stopped,
+                            :condition, :lrd, :metadata)",
+                        params,
+                    )?,
+                };
+            }
+        }
+    }
+
+    let mut num_renumbered = 0;
+    for (position, (hash, old_position)) in queue.iter().enumerate() {
+        let position = position as i64;
+        if *old_position == position {
+            continue;
+        }
+        if verbose {
+            println!("{}: queue position {} -> {}", hash, old_position, position);
+        }
+        num_renumbered += 1;
+        if run.dry_run {
+            continue;
+        }
+
+        transaction.execute(
+            "UPDATE torrents SET queue_position = :position WHERE torrent_id = :hash",
+            named_params! {":position": position, ":hash": hash},
+        )?;
+    }
+
+    if !run.dry_run {
+        transaction.commit()?;
+    }
+
+    let verb = match run.dry_run {
+        true => "would be",
+        false => "were",
+    };
+    let singular = match run.dry_run {
+        true => "would be",
+        false => "was",
+    };
+    for (count, action) in [
+        (num_inserted, "added"),
+        (num_replaced, "replaced"),
+        (num_combined, "merged"),
+        (num_skipped, "left as in the target"),
+        (num_remapped, "given mapped paths"),
+        (num_renumbered, "renumbered in the queue"),
+    ] {
+        match count {
+            0 => {}
+            1 => println!("Merge: 1 torrent {} {}", singular, action),
+            _ => println!("Merge: {} torrents {} {}", count, verb, action),
+        }
+    }
+    if num_inserted + num_replaced + num_combined == 0 {
+        println!("Merge: no torrents need merging");
+    }
+
+    Ok(())
+}
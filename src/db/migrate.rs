@@ -0,0 +1,227 @@
+//! Tools for migrating torrent paths between machines
+
+use crate::db::db_structs::{FastresumeData, FetchedPathData};
+use crate::path::{QbPath, Separator};
+use rusqlite::{named_params, Connection};
+use serde_rusqlite::from_rows;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+/// Maps a drive, network share or other path prefix to a new location
+#[derive(Debug)]
+pub struct PathMapping {
+    /// Existing path prefix, e.g. `D:\` or `\\nas\share`
+    pub from: QbPath,
+    /// New path prefix, e.g. `/mnt/d`
+    pub to: QbPath,
+}
+
+/// Path migration information
+#[derive(Debug)]
+pub struct Migration {
+    /// Path mappings to apply, the longest matching prefix wins
+    pub mappings: Vec<PathMapping>,
+    /// Separator to use in the fastresume save_path, should default to current OS style
+    pub separator: Separator,
+}
+
+impl Migration {
+    /// Returns the mapping with the longest prefix matching the path, if any
+    fn mapping_for(&self, path: &QbPath) -> Option<&PathMapping> {
+        self.mappings
+            .iter()
+            .filter(|mapping| path.starts_with(&mapping.from))
+            .max_by_key(|mapping| mapping.from.components.len())
+    }
+
+    /// Maps a path, returning `None` if no mapping applies
+    pub(crate) fn map(&self, path: &QbPath) -> Option<QbPath> {
+        self.mapping_for(path)
+            .and_then(|mapping| path.replace_prefix(&mapping.from, &mapping.to))
+    }
+}
+
+/// Scans every distinct root of the save paths and download paths in the database and, for each
+/// mapping given, rewrites `target_save_path`, `download_path` and the save_path within
+/// `libtorrent_resume_data` in one step.
+///
+/// Without any mappings the roots are only reported, so the scan can be used to decide which
+/// mappings are needed. Paths that no mapping applies to are left untouched.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::migrate::{migrate_paths, Migration, PathMapping};
+/// use qbfrt::path::{QbPath, Separator};
+/// let migration = Migration {
+///     mappings: vec![PathMapping {
+///         from: QbPath::parse("D:\\"),
+///         to: QbPath::parse("/mnt/d"),
+///     }],
+///     separator: Separator::Unix,
+/// };
+/// migrate_paths(&connection, migration, false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the distinct save paths under each root, and the
+/// torrent hash and new paths for every updated torrent.
+pub fn migrate_paths(
+    db: &Connection,
+    migration: Migration,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    println!("Migrate: scanning save paths and download paths");
+
+    let mut search_stmt = db.prepare(
+        "SELECT id, torrent_id, target_save_path, download_path, libtorrent_resume_data
+        FROM torrents",
+    )?;
+    let all_torrents =
+        from_rows::<FetchedPathData>(search_stmt.query([])?).collect::<Result<Vec<_>, _>>()?;
+
+    // Roots are keyed by how they are displayed, each with the distinct paths found under it
+    let mut roots: BTreeMap<String, (QbPath, BTreeMap<String, usize>)> = BTreeMap::new();
+    for torrent in &all_torrents {
+        let libtorrent_resume_data: FastresumeData =
+            serde_bencode::from_bytes(torrent.libtorrent_resume_data.as_slice())?;
+
+        // The same path is usually stored twice, so each distinct path is only counted once per torrent
+        let paths: BTreeSet<String> = [
+            torrent
+                .target_save_path
+                .as_deref()
+                .map(QbPath::parse_portable),
+            torrent.download_path.as_deref().map(QbPath::parse_portable),
+            Some(QbPath::parse(&libtorrent_resume_data.save_path)),
+        ]
+        .into_iter()
+        .flatten()
+        .map(|path| path.to_portable())
+        .filter(|path| !path.is_empty())
+        .collect();
+        for path in paths {
+            let parsed = QbPath::parse_portable(&path);
+            let root = parsed.root_path();
+            let display_separator = if root.is_windows() {
+                Separator::Windows
+            } else {
+                Separator::Unix
+            };
+            let (_, paths) = roots
+                .entry(root.to_native(display_separator))
+                .or_insert_with(|| (root, BTreeMap::new()));
+            *paths.entry(path).or_default() += 1;
+        }
+    }
+
+    match roots.len() {
+        1 => println!("Migrate: found 1 path root"),
+        _ => println!("Migrate: found {} path roots", roots.len()),
+    }
+    for (display, (root, paths)) in &roots {
+        match migration.mapping_for(root) {
+            Some(mapping) => println!(
+                "Migrate: {} -> {}",
+                display,
+                mapping.to.to_native(migration.separator)
+            ),
+            None => {
+                // A mapping may still cover some of the paths below the root
+                let mapped = paths
+                    .keys()
+                    .filter(|path| {
+                        migration
+                            .mapping_for(&QbPath::parse_portable(path))
+                            .is_some()
+                    })
+                    .count();
+                match mapped {
+                    0 => println!("Migrate: {} is not mapped", display),
+                    _ => println!(
+                        "Migrate: {} is partially mapped ({} of {} paths)",
+                        display,
+                        mapped,
+                        paths.len()
+                    ),
+                }
+            }
+        }
+
+        if verbose {
+            for (path, count) in paths {
+                println!("Migrate:     {} ({})", path, count);
+            }
+        }
+    }
+
+    if migration.mappings.is_empty() {
+        println!("Migrate: no mappings were given, nothing was changed");
+        return Ok(());
+    }
+
+    let mut num_updated = 0;
+    for torrent in all_torrents {
+        let mut libtorrent_resume_data: FastresumeData =
+            serde_bencode::from_bytes(torrent.libtorrent_resume_data.as_slice())?;
+
+        // qB always stores "target_save_path" and "download_path" with Unix-style separators
+        let map_portable = |path: Option<String>| -> (Option<String>, bool) {
+            match path
+                .as_deref()
+                .map(QbPath::parse_portable)
+                .and_then(|p| migration.map(&p))
+            {
+                Some(mapped) => (Some(mapped.to_portable()), true),
+                None => (path, false),
+            }
+        };
+        let (target_save_path, target_changed) = map_portable(torrent.target_save_path);
+        let (download_path, download_changed) = map_portable(torrent.download_path);
+
+        let mut save_path_changed = false;
+        if let Some(mapped) = migration.map(&QbPath::parse(&libtorrent_resume_data.save_path)) {
+            libtorrent_resume_data.save_path = mapped.to_native(migration.separator);
+            save_path_changed = true;
+        }
+
+        if !(target_changed || download_changed || save_path_changed) {
+            continue;
+        }
+
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET target_save_path = :tsp, download_path = :dp, libtorrent_resume_data = :lrd
+                WHERE id = :id
+                RETURNING torrent_id;",
+        )?;
+        update_stmt.query_row(
+            named_params! {":tsp": target_save_path, ":dp": download_path, ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?, ":id": torrent.id},
+            |row| {
+                let updated_row_id = row.get::<usize, String>(0)?;
+
+                if verbose {
+                    println!("Migrate: updated paths for {}", updated_row_id);
+                    if let Some(target_save_path) = &target_save_path {
+                        println!("{}: new target_save_path is {}", updated_row_id, target_save_path);
+                    }
+                    if let Some(download_path) = &download_path {
+                        println!("{}: new download_path is {}", updated_row_id, download_path);
+                    }
+                    println!("{}: new libtorrent_resume_data path is {}", updated_row_id, libtorrent_resume_data.save_path);
+                }
+
+                num_updated += 1;
+
+                Ok(())
+            },
+        )?;
+    }
+
+    match num_updated {
+        0 => println!("Migrate: no torrents were updated"),
+        1 => println!("Migrate: 1 torrent was updated"),
+        _ => println!("Migrate: {} torrents were updated", num_updated),
+    }
+
+    Ok(())
+}
@@ -18,15 +18,41 @@
 //!     process::exit(1);
 //! });
 
-use crate::config::Config;
+use crate::config::{Command, Config};
 use rusqlite::{Connection, OpenFlags, Result};
 use std::error::Error;
 use std::fs;
 use std::path::Path;
 
+pub mod category;
+pub mod counters;
 pub mod db_structs;
+pub mod deluge;
+pub mod diff;
+pub mod doctor;
+pub mod export;
+pub mod filter;
+pub mod import;
+pub mod limits;
+pub mod merge;
+pub mod migrate;
+pub mod passkey;
+pub mod queue;
+pub mod rename;
+pub mod rtorrent;
+pub mod rules;
 pub mod save_path;
+pub mod state;
+pub mod stats;
+pub mod tag;
+#[cfg(test)]
+mod test_fixtures;
+pub mod tracker_policy;
+pub mod tracker_report;
 pub mod tracker_url;
+pub mod trackers;
+pub mod transmission;
+pub mod webseeds;
 
 /// qB torrents.db struct
 pub struct DB {}
@@ -87,4 +113,28 @@ impl DB {
                 | OpenFlags::SQLITE_OPEN_NO_MUTEX,
         )?)
     }
+
+    /// Opens the torrents.db SQLite database without write access and returns the connection
+    ///
+    /// Used by commands that only inspect the database, so no backup is needed beforehand.
+    ///
+    /// ## Example
+    /// ```rs
+    /// let conn = DB::connect_read_only(&config).unwrap_or_else(|err| {
+    ///     println!("Could not connect to database: {err}");
+    ///     process::exit(1);
+    /// });
+    /// ```
+    pub fn connect_read_only(config: &Config) -> Result<Connection, Box<dyn Error>> {
+        if !config.command.as_ref().is_some_and(Command::is_quiet) {
+            println!("Opening database read-only...");
+        }
+
+        Ok(Connection::open_with_flags(
+            &config.db_file,
+            OpenFlags::SQLITE_OPEN_READ_ONLY
+                | OpenFlags::SQLITE_OPEN_URI
+                | OpenFlags::SQLITE_OPEN_NO_MUTEX,
+        )?)
+    }
 }
@@ -0,0 +1,200 @@
+//! Tools for rotating private tracker passkeys
+
+use crate::db::filter::{fetch_torrents, TorrentFilter};
+use crate::db::tracker_url::{host_matches, tracker_host};
+use rusqlite::{named_params, Connection};
+use std::collections::BTreeMap;
+use std::error::Error;
+use url::Url;
+
+/// Query parameters private trackers commonly put the passkey in
+const PASSKEY_PARAMS: [&str; 5] = ["passkey", "pk", "authkey", "torrent_pass", "key"];
+
+/// Passkey change information
+#[derive(Debug)]
+pub struct PasskeyChange {
+    /// Tracker domain, subdomains included
+    pub host: String,
+    /// Existing passkey, detected from the announce URL if not given
+    pub old: Option<String>,
+    /// New passkey
+    pub new: String,
+}
+
+/// Returns true if a URL component looks like a passkey, a long run of letters and digits
+fn is_passkey_like(component: &str) -> bool {
+    component.len() >= 16 && component.chars().all(|c| c.is_ascii_alphanumeric())
+}
+
+/// Replaces the passkey in an announce URL, returning `None` if the URL has no matching passkey
+///
+/// Only whole path segments and query parameter values are replaced. If `old` is not given, path
+/// segments that look like a passkey and values of the usual passkey query parameters are replaced.
+/// The rest of the URL is kept byte for byte, since trackers may compare announce URLs exactly.
+pub fn replace_passkey(announce_url: &str, old: Option<&str>, new: &str) -> Option<String> {
+    Url::parse(announce_url.trim()).ok()?;
+    let mut replaced = false;
+
+    // Split off the scheme and authority, and the fragment, which are kept as they are
+    let path_start = announce_url
+        .find("://")
+        .map(|index| index + 3)
+        .and_then(|authority| {
+            announce_url[authority..]
+                .find(['/', '?', '#'])
+                .map(|index| authority + index)
+        })
+        .unwrap_or(announce_url.len());
+    let (prefix, rest) = announce_url.split_at(path_start);
+    let (rest, fragment) = match rest.find('#') {
+        Some(index) => rest.split_at(index),
+        None => (rest, ""),
+    };
+    let (path, query) = match rest.split_once('?') {
+        Some((path, query)) => (path, Some(query)),
+        None => (rest, None),
+    };
+
+    let path = path
+        .split('/')
+        .map(|segment| {
+            let matches = match old {
+                Some(old) => segment == old,
+                None => is_passkey_like(segment),
+            };
+            match matches && segment != new {
+                true => {
+                    replaced = true;
+                    new
+                }
+                false => segment,
+            }
+        })
+        .collect::<Vec<&str>>()
+        .join("/");
+
+    let query = query.map(|query| {
+        query
+            .split('&')
+            .map(|pair| match pair.split_once('=') {
+                Some((name, value)) => {
+                    let matches = match old {
+                        Some(old) => value == old,
+                        None => PASSKEY_PARAMS.contains(&name.to_lowercase().as_str()),
+                    };
+                    match matches && value != new {
+                        true => {
+                            replaced = true;
+                            format!("{name}={new}")
+                        }
+                        false => pair.to_string(),
+                    }
+                }
+                None => pair.to_string(),
+            })
+            .collect::<Vec<String>>()
+            .join("&")
+    });
+
+    if !replaced {
+        return None;
+    }
+
+    Some(match query {
+        Some(query) => format!("{prefix}{path}?{query}{fragment}"),
+        None => format!("{prefix}{path}{fragment}"),
+    })
+}
+
+/// Replaces the passkey in the announce URLs of every torrent tracked by the given host
+///
+/// Unlike the tracker url replacement, which replaces a string anywhere in any tracker URL, only
+/// trackers on the given host are changed, and only their passkey path segment or query parameter.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::passkey::{change_passkey, PasskeyChange};
+/// let change = PasskeyChange {
+///     host: String::from("private.tracker"),
+///     old: None,
+///     new: String::from("0123456789abcdef0123456789abcdef"),
+/// };
+/// change_passkey(&connection, change, &TorrentFilter::default(), false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the torrent hash with the old and new URL of every
+/// changed tracker.
+pub fn change_passkey(
+    db: &Connection,
+    change: PasskeyChange,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    println!("Passkey: replacing passkeys for {}", change.host);
+
+    let torrents = fetch_torrents(db, filter)?;
+
+    let mut num_updated = 0;
+    let mut host_counts: BTreeMap<String, usize> = BTreeMap::new();
+    for torrent in torrents {
+        let mut libtorrent_resume_data = torrent.fastresume;
+
+        let mut changed_hosts = Vec::new();
+        for tier in libtorrent_resume_data.trackers.iter_mut() {
+            for tracker in tier.iter_mut() {
+                let Some(host) = tracker_host(tracker) else {
+                    continue;
+                };
+                if !host_matches(&host, &change.host) {
+                    continue;
+                }
+                let Some(new_url) = replace_passkey(tracker, change.old.as_deref(), &change.new)
+                else {
+                    continue;
+                };
+
+                if verbose {
+                    println!("{}: {} -> {}", torrent.data.torrent_id, tracker, new_url);
+                }
+                *tracker = new_url;
+                if !changed_hosts.contains(&host) {
+                    changed_hosts.push(host);
+                }
+            }
+        }
+
+        if changed_hosts.is_empty() {
+            continue;
+        }
+        for host in changed_hosts {
+            *host_counts.entry(host).or_default() += 1;
+        }
+
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET libtorrent_resume_data = :lrd
+                WHERE id = :id",
+        )?;
+        update_stmt.execute(named_params! {
+            ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?,
+            ":id": torrent.data.id,
+        })?;
+
+        num_updated += 1;
+    }
+
+    for (host, count) in host_counts {
+        match count {
+            1 => println!("Passkey: {} updated on 1 torrent", host),
+            _ => println!("Passkey: {} updated on {} torrents", host, count),
+        }
+    }
+    match num_updated {
+        0 => println!("Passkey: no torrents were updated"),
+        1 => println!("Passkey: 1 torrent was updated"),
+        _ => println!("Passkey: {} torrents were updated", num_updated),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,164 @@
+//! Tools for reordering the download queue
+
+use crate::db::filter::{fetch_torrents, Torrent, TorrentFilter};
+use rusqlite::{named_params, Connection};
+use std::cmp::Reverse;
+use std::error::Error;
+
+/// Torrent property to sort the queue by
+#[derive(Debug, Clone, Copy)]
+pub enum SortKey {
+    /// Total size of the torrent's files, smallest first
+    Size,
+    /// Date the torrent was added, oldest first
+    Added,
+    /// Case-insensitive torrent name
+    Name,
+    /// Category, then case-insensitive torrent name
+    Category,
+}
+
+impl SortKey {
+    /// Parses a sort key name: 'size', 'added', 'name' or 'category'
+    pub fn parse(key: &str) -> Result<SortKey, String> {
+        match key {
+            "size" => Ok(SortKey::Size),
+            "added" => Ok(SortKey::Added),
+            "name" => Ok(SortKey::Name),
+            "category" => Ok(SortKey::Category),
+            _ => Err(format!(
+                "{key} is not a valid sort key, use 'size', 'added', 'name' or 'category'"
+            )),
+        }
+    }
+}
+
+/// Queue operations
+#[derive(Debug)]
+pub enum QueueOperation {
+    /// Sort the selected torrents among the queue positions they already occupy
+    Sort {
+        /// Property to sort by
+        key: SortKey,
+        /// Sort in descending order
+        reverse: bool,
+    },
+    /// Move the selected torrents to the top of the queue, keeping their order
+    Top,
+    /// Move the selected torrents to the bottom of the queue, keeping their order
+    Bottom,
+    /// Only renumber the queue so positions are contiguous
+    Compact,
+}
+
+/// Sorts torrents by the given key, keeping the current order for ties
+fn sort_torrents(torrents: &mut [&Torrent], key: SortKey, reverse: bool) {
+    match key {
+        SortKey::Size => sort_by_key(torrents, reverse, |torrent| torrent.total_size()),
+        SortKey::Added => sort_by_key(torrents, reverse, |torrent| torrent.fastresume.added_time),
+        SortKey::Name => sort_by_key(torrents, reverse, |torrent| {
+            torrent.display_name().to_lowercase()
+        }),
+        SortKey::Category => sort_by_key(torrents, reverse, |torrent| {
+            (
+                torrent.data.category.clone().unwrap_or_default(),
+                torrent.display_name().to_lowercase(),
+            )
+        }),
+    }
+}
+
+/// Stable sort by a key, reversing the key rather than the result so ties keep their order
+fn sort_by_key<K: Ord>(torrents: &mut [&Torrent], reverse: bool, key: impl Fn(&Torrent) -> K) {
+    match reverse {
+        true => torrents.sort_by_cached_key(|torrent| Reverse(key(torrent))),
+        false => torrents.sort_by_cached_key(|torrent| key(torrent)),
+    }
+}
+
+/// Reorders the download queue and renumbers it so positions run from 0 without gaps
+///
+/// Only queued torrents are affected, seeding torrents with a `queue_position` of `-1` are left
+/// alone. Torrents that share a position or follow a gap keep their relative order, with ties
+/// broken by database row id.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::queue::{reorder_queue, QueueOperation, SortKey};
+/// let operation = QueueOperation::Sort {
+///     key: SortKey::Size,
+///     reverse: false,
+/// };
+/// reorder_queue(&connection, operation, &TorrentFilter::default(), false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the old and new queue position of every moved
+/// torrent.
+pub fn reorder_queue(
+    db: &Connection,
+    operation: QueueOperation,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    println!("Queue: reordering queued torrents");
+
+    let all_torrents = fetch_torrents(db, &TorrentFilter::default())?;
+    let mut queued: Vec<&Torrent> = all_torrents
+        .iter()
+        .filter(|torrent| torrent.data.queue_position >= 0)
+        .collect();
+    queued.sort_by_key(|torrent| (torrent.data.queue_position, torrent.data.id));
+
+    let (selected, others): (Vec<&Torrent>, Vec<&Torrent>) =
+        queued.iter().partition(|torrent| filter.matches(torrent));
+
+    let new_order = match operation {
+        QueueOperation::Sort { key, reverse } => {
+            let mut sorted = selected;
+            sort_torrents(&mut sorted, key, reverse);
+            let mut sorted = sorted.into_iter();
+            queued
+                .iter()
+                .map(|torrent| match filter.matches(torrent) {
+                    true => sorted.next().unwrap_or(*torrent),
+                    false => *torrent,
+                })
+                .collect()
+        }
+        QueueOperation::Top => [selected, others].concat(),
+        QueueOperation::Bottom => [others, selected].concat(),
+        QueueOperation::Compact => queued,
+    };
+
+    let mut num_moved = 0;
+    for (position, torrent) in new_order.iter().enumerate() {
+        let position = position as i64;
+        if torrent.data.queue_position == position {
+            continue;
+        }
+
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET queue_position = :position
+                WHERE id = :id",
+        )?;
+        update_stmt.execute(named_params! {":position": position, ":id": torrent.data.id})?;
+
+        if verbose {
+            println!(
+                "{}: queue position {} -> {}",
+                torrent.data.torrent_id, torrent.data.queue_position, position
+            );
+        }
+        num_moved += 1;
+    }
+
+    match num_moved {
+        0 => println!("Queue: no torrents were moved"),
+        1 => println!("Queue: 1 torrent was moved"),
+        _ => println!("Queue: {} torrents were moved", num_moved),
+    }
+
+    Ok(())
+}
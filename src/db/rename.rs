@@ -0,0 +1,317 @@
+//! Tools for renaming torrents and the files they contain
+
+use crate::db::db_structs::TorrentMetadata;
+use crate::db::filter::{fetch_torrents, TorrentFilter};
+use crate::path::{is_windows_style, mapped_file_paths};
+use rusqlite::{named_params, Connection};
+use std::collections::BTreeSet;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// A single file rename, with paths relative to the save path as they are before renaming
+#[derive(Debug)]
+pub struct FileRename {
+    /// Current path of the file
+    pub from: String,
+    /// New path of the file
+    pub to: String,
+}
+
+/// Rename information
+#[derive(Debug, Default)]
+pub struct RenameRequest {
+    /// New display name, an empty name restores the name from the metadata
+    pub name: Option<String>,
+    /// New name for the root folder of a multi-file torrent
+    pub root: Option<String>,
+    /// Individual file renames
+    pub files: Vec<FileRename>,
+    /// Also rename the files on disk
+    pub move_files: bool,
+}
+
+impl RenameRequest {
+    /// Returns true if nothing would be renamed
+    pub fn is_empty(&self) -> bool {
+        self.name.is_none() && self.root.is_none() && self.files.is_empty()
+    }
+}
+
+/// Converts a relative path to '/' separators without leading or trailing separators
+fn normalize_relative(path: &str) -> String {
+    path.replace('\\', "/").trim_matches('/').to_string()
+}
+
+/// Returns an error if a new relative path is empty or leaves the save path
+fn check_relative(path: &str) -> Result<(), String> {
+    if path.is_empty()
+        || path
+            .split('/')
+            .any(|component| component.is_empty() || component == "..")
+        || is_windows_style(path)
+    {
+        return Err(format!("{path:?} is not a valid relative file path"));
+    }
+    Ok(())
+}
+
+/// Returns the location of a relative '/' separated path below the save path
+fn disk_path(save_path: &Path, relative: &str) -> PathBuf {
+    relative
+        .split('/')
+        .fold(save_path.to_path_buf(), |path, component| {
+            path.join(component)
+        })
+}
+
+/// Removes the directories containing a path while they are empty, stopping at the save path
+fn remove_empty_parents(save_path: &Path, path: &Path) {
+    let mut directory = path.parent();
+    while let Some(path) = directory {
+        if path == save_path || fs::remove_dir(path).is_err() {
+            break;
+        }
+        directory = path.parent();
+    }
+}
+
+/// Moves files on disk, moving already moved files back if any move fails
+///
+/// Every file is first moved to a temporary name next to it and then to its new path, so files can
+/// swap paths or take over the old path of another file in the same batch.
+fn move_files(save_path: &Path, moves: &[(&String, &String)]) -> Result<(), Box<dyn Error>> {
+    let sources: BTreeSet<&String> = moves.iter().map(|(from, _)| *from).collect();
+    for (_, to) in moves {
+        if !sources.contains(to) && disk_path(save_path, to).exists() {
+            return Err(format!("{} already exists", disk_path(save_path, to).display()).into());
+        }
+    }
+
+    let staged: Vec<(PathBuf, PathBuf, PathBuf)> = moves
+        .iter()
+        .enumerate()
+        .map(|(index, (from, to))| {
+            let source = disk_path(save_path, from);
+            let mut temporary = source.file_name().unwrap_or_default().to_os_string();
+            temporary.push(format!(".qbfrt-rename-{index}"));
+            let temporary = source.with_file_name(temporary);
+            (source, temporary, disk_path(save_path, to))
+        })
+        .collect();
+    let steps = staged
+        .iter()
+        .map(|(source, temporary, _)| (source, temporary))
+        .chain(
+            staged
+                .iter()
+                .map(|(_, temporary, destination)| (temporary, destination)),
+        );
+
+    let mut moved: Vec<(&PathBuf, &PathBuf)> = Vec::new();
+    for (source, destination) in steps {
+        let result = destination
+            .parent()
+            .map_or(Ok(()), fs::create_dir_all)
+            .and_then(|_| fs::rename(source, destination));
+        if let Err(err) = result {
+            remove_empty_parents(save_path, destination);
+            for (source, destination) in moved.iter().rev() {
+                let _ = fs::rename(destination, source);
+                remove_empty_parents(save_path, destination);
+            }
+            return Err(format!("could not move {}: {err}", source.display()).into());
+        }
+        moved.push((source, destination));
+    }
+
+    for (source, _, _) in &staged {
+        remove_empty_parents(save_path, source);
+    }
+
+    Ok(())
+}
+
+/// Renames a single torrent, its root folder or individual files
+///
+/// The filter must select exactly one torrent. The display name is stored in the `name` column,
+/// while renamed files are stored in the `mapped_files` list of `libtorrent_resume_data`, which holds
+/// the path of every file relative to the save path. The fastresume piece data is left untouched, so
+/// qB will not recheck the torrent as long as the files are found at their new paths. With
+/// `move_files` set the files are renamed on disk too, otherwise they have to be moved by hand
+/// before qB is started.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::rename::{rename_torrent, RenameRequest};
+/// let request = RenameRequest {
+///     root: Some(String::from("New Folder")),
+///     move_files: true,
+///     ..Default::default()
+/// };
+/// rename_torrent(&connection, request, &filter, false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the old and new path of every renamed file.
+pub fn rename_torrent(
+    db: &Connection,
+    request: RenameRequest,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    let mut torrents = fetch_torrents(db, filter)?;
+    if torrents.len() != 1 {
+        return Err(format!(
+            "rename needs exactly one selected torrent, {} matched",
+            torrents.len()
+        )
+        .into());
+    }
+    let torrent = torrents.remove(0);
+    let mut data = torrent.data;
+    let mut libtorrent_resume_data = torrent.fastresume;
+
+    println!("Rename: renaming {}", data.torrent_id);
+
+    let mut name_changed = false;
+    if let Some(name) = request.name {
+        let name = (!name.is_empty()).then_some(name);
+        if name != data.name {
+            println!(
+                "Rename: name {} -> {}",
+                data.name.as_deref().unwrap_or(&libtorrent_resume_data.name),
+                name.as_deref().unwrap_or(&libtorrent_resume_data.name)
+            );
+            data.name = name;
+            name_changed = true;
+        }
+    }
+
+    let mut num_renamed = 0;
+    let mut moves: Vec<(String, String)> = Vec::new();
+    if request.root.is_some() || !request.files.is_empty() {
+        let metadata: TorrentMetadata = match data.metadata.as_deref() {
+            Some(metadata) => serde_bencode::from_bytes(metadata)?,
+            None => return Err("torrent has no metadata, its files are unknown".into()),
+        };
+        let original_paths = metadata.file_paths();
+        let current_paths: Vec<String> = mapped_file_paths(
+            libtorrent_resume_data.mapped_files.as_deref(),
+            &original_paths,
+            is_windows_style(&libtorrent_resume_data.save_path),
+        )
+        .iter()
+        .map(|path| normalize_relative(path))
+        .collect();
+        let mut new_paths = current_paths.clone();
+
+        if let Some(root) = request.root {
+            let root = normalize_relative(&root);
+            check_relative(&root)?;
+            if root.contains('/') {
+                return Err(format!("root folder {root:?} cannot contain separators").into());
+            }
+            if !new_paths.iter().all(|path| path.contains('/')) {
+                return Err("torrent has no root folder".into());
+            }
+            for path in new_paths.iter_mut() {
+                let (_, rest) = path.split_once('/').unwrap_or_default();
+                *path = format!("{root}/{rest}");
+            }
+        }
+
+        for file in request.files {
+            let from = normalize_relative(&file.from);
+            let to = normalize_relative(&file.to);
+            check_relative(&to)?;
+            let Some(index) = current_paths.iter().position(|path| *path == from) else {
+                return Err(format!("{from} is not a file of the torrent").into());
+            };
+            new_paths[index] = to;
+        }
+
+        for (index, path) in new_paths.iter().enumerate() {
+            if new_paths[..index].contains(path) {
+                return Err(format!("more than one file would be renamed to {path}").into());
+            }
+        }
+
+        let renames: Vec<(&String, &String)> = current_paths
+            .iter()
+            .zip(&new_paths)
+            .filter(|(current, new)| current != new)
+            .collect();
+        num_renamed = renames.len();
+
+        if verbose {
+            for (current, new) in &renames {
+                println!("{}: {} -> {}", data.torrent_id, current, new);
+            }
+        }
+
+        if request.move_files {
+            moves = renames
+                .iter()
+                .map(|(current, new)| (current.to_string(), new.to_string()))
+                .collect();
+        }
+
+        // libtorrent stores mapped paths with the separator of the platform it runs on
+        let separator = match is_windows_style(&libtorrent_resume_data.save_path) {
+            true => "\\",
+            false => "/",
+        };
+        libtorrent_resume_data.mapped_files = match new_paths == original_paths {
+            true => None,
+            false => Some(
+                new_paths
+                    .iter()
+                    .map(|path| path.replace('/', separator))
+                    .collect(),
+            ),
+        };
+    }
+
+    if !name_changed && num_renamed == 0 {
+        println!("Rename: nothing needs renaming");
+        return Ok(());
+    }
+
+    // The row is updated before the files are moved, and only committed once they were, so the
+    // database never points at paths that do not exist
+    let transaction = db.unchecked_transaction()?;
+    transaction.execute(
+        "UPDATE torrents
+            SET name = :name, libtorrent_resume_data = :lrd
+            WHERE id = :id",
+        named_params! {
+            ":name": data.name,
+            ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?,
+            ":id": data.id,
+        },
+    )?;
+    let save_path = Path::new(&libtorrent_resume_data.save_path);
+    let moves: Vec<(&String, &String)> = moves.iter().map(|(from, to)| (from, to)).collect();
+    if !moves.is_empty() {
+        move_files(save_path, &moves)?;
+    }
+    if let Err(err) = transaction.commit() {
+        let undo: Vec<(&String, &String)> = moves.iter().map(|(from, to)| (*to, *from)).collect();
+        if !undo.is_empty() {
+            move_files(save_path, &undo)?;
+        }
+        return Err(err.into());
+    }
+
+    match num_renamed {
+        0 => {}
+        1 => println!("Rename: 1 file was renamed"),
+        _ => println!("Rename: {} files were renamed", num_renamed),
+    }
+    if num_renamed > 0 && !request.move_files {
+        println!("Rename: files were not moved on disk, move them before starting qBittorrent");
+    }
+
+    Ok(())
+}
@@ -0,0 +1,462 @@
+//! Tools for reading and writing torrents in an rTorrent session directory
+
+use crate::db::export::{pack_bitfield, ExportedTorrent};
+use crate::db::import::{read_torrent_file, unpack_bitfield, ImportedTorrent};
+use crate::path::QbPath;
+use serde_bencode::value::Value;
+use serde_bytes::ByteBuf;
+use serde_derive::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+/// rTorrent session state, `<hash>.torrent.rtorrent`
+///
+/// rTorrent fills in defaults for any field that is missing.
+#[derive(Serialize, Deserialize, Debug)]
+struct RtorrentSession {
+    /// Download directory, including the root folder for torrents with multiple files
+    directory: Option<String>,
+    /// First custom field, used for labels by ruTorrent and most other front ends
+    custom1: Option<String>,
+    /// 1 if the torrent is started, 0 if it is stopped
+    state: Option<i64>,
+    /// 1 if every piece has been downloaded
+    complete: Option<i64>,
+    /// Total uploaded in bytes, saved by newer versions
+    total_uploaded: Option<u64>,
+    /// Total downloaded in bytes, saved by newer versions
+    total_downloaded: Option<u64>,
+    /// Date the torrent was last started
+    #[serde(rename = "timestamp.started")]
+    started: Option<i64>,
+    /// Date the torrent was completed
+    #[serde(rename = "timestamp.finished")]
+    finished: Option<i64>,
+    /// Custom fields set by front ends, ruTorrent saves the date the torrent was added as `addtime`
+    custom: Option<BTreeMap<String, ByteBuf>>,
+}
+
+/// libtorrent resume data saved by rTorrent, `<hash>.torrent.libtorrent_resume`
+#[derive(Serialize, Deserialize, Debug)]
+struct RtorrentResume {
+    /// Downloaded pieces as a bitfield, or the number of downloaded pieces if all or none are
+    bitfield: Option<Value>,
+    /// Pieces that may not have been written completely, as 4 byte big-endian indices
+    uncertain_pieces: Option<ByteBuf>,
+    /// State of every file, the bitfield is only trusted for files whose modification time matches
+    files: Option<Vec<RtorrentFile>>,
+    /// Trackers keyed by URL, including trackers added in rTorrent
+    trackers: Option<BTreeMap<String, RtorrentTracker>>,
+}
+
+/// File state within rTorrent resume data
+#[derive(Serialize, Deserialize, Debug)]
+struct RtorrentFile {
+    /// Download priority, 0 to skip the file, 1 for normal and 2 for high
+    priority: Option<i64>,
+    /// Modification time of the file on disk
+    mtime: Option<i64>,
+}
+
+/// Tracker state within rTorrent resume data
+#[derive(Serialize, Deserialize, Debug)]
+struct RtorrentTracker {
+    /// 0 if the tracker was disabled
+    enabled: Option<i64>,
+}
+
+/// Decodes a percent-encoded label, as saved by ruTorrent
+fn decode_label(label: &str) -> String {
+    let bytes = label.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut index = 0;
+    while index < bytes.len() {
+        let byte = match (bytes[index], label.get(index + 1..index + 3)) {
+            (b'%', Some(hex)) => u8::from_str_radix(hex, 16).ok(),
+            _ => None,
+        };
+        match byte {
+            Some(byte) => {
+                decoded.push(byte);
+                index += 3;
+            }
+            None => {
+                decoded.push(bytes[index]);
+                index += 1;
+            }
+        }
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+/// Percent-encodes a label, as ruTorrent expects
+fn encode_label(label: &str) -> String {
+    label
+        .bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Converts the rTorrent resume bitfield to piece completion, leaving out uncertain pieces
+fn read_pieces(resume: &RtorrentResume, num_pieces: usize) -> Vec<bool> {
+    let mut pieces = match &resume.bitfield {
+        Some(Value::Int(count)) if *count == num_pieces as i64 => vec![true; num_pieces],
+        Some(Value::Int(0)) => vec![false; num_pieces],
+        Some(Value::Bytes(bitfield)) if bitfield.len() == num_pieces.div_ceil(8) => {
+            unpack_bitfield(bitfield, num_pieces)
+        }
+        _ => return Vec::new(),
+    };
+    for index in resume
+        .uncertain_pieces
+        .iter()
+        .flat_map(|bytes| bytes.chunks_exact(4))
+    {
+        let index = u32::from_be_bytes([index[0], index[1], index[2], index[3]]) as usize;
+        if let Some(piece) = pieces.get_mut(index) {
+            *piece = false;
+        }
+    }
+    pieces
+}
+
+/// Reads a single torrent from its .torrent file and rTorrent session files
+fn read_torrent(torrent_file: &Path) -> Result<ImportedTorrent, Box<dyn Error>> {
+    let (contents, metadata) = read_torrent_file(torrent_file)?;
+    let session: RtorrentSession =
+        serde_bencode::from_bytes(&fs::read(with_suffix(torrent_file, ".rtorrent"))?)?;
+    let resume_file = with_suffix(torrent_file, ".libtorrent_resume");
+    let resume: Option<RtorrentResume> = match resume_file.is_file() {
+        true => Some(serde_bencode::from_bytes(&fs::read(resume_file)?)?),
+        false => None,
+    };
+    let Some(directory) = session.directory.filter(|directory| !directory.is_empty()) else {
+        return Err("session has no directory".into());
+    };
+
+    let original_paths = metadata.file_paths();
+    let original_name = metadata.name();
+    let num_pieces = metadata.num_pieces();
+
+    // The directory of a torrent with multiple files is its root folder, which may have been renamed
+    let directory = directory.trim_end_matches('/');
+    let multi_file = original_paths.iter().all(|path| path.contains('/'));
+    let (save_path, root) = match (multi_file, directory.rsplit_once('/')) {
+        (true, Some((parent, root))) => (parent.to_string(), Some(root.to_string())),
+        _ => (directory.to_string(), None),
+    };
+    let save_path = match save_path.is_empty() {
+        true => String::from("/"),
+        false => save_path,
+    };
+
+    let mut torrent = ImportedTorrent::new(contents, metadata, save_path);
+    if let Some(root) = root.filter(|root| *root != original_name) {
+        torrent.mapped_files = Some(
+            original_paths
+                .iter()
+                .map(|path| match path.split_once('/') {
+                    Some((_, rest)) => format!("{root}/{rest}"),
+                    None => path.clone(),
+                })
+                .collect(),
+        );
+    }
+    torrent.category = session
+        .custom1
+        .map(|label| decode_label(&label))
+        .filter(|label| !label.is_empty());
+
+    if let Some(resume) = &resume {
+        torrent.pieces = read_pieces(resume, num_pieces);
+
+        // Disabled trackers are dropped, and trackers added in rTorrent get a tier of their own
+        if let Some(trackers) = &resume.trackers {
+            let enabled = |url: &String| {
+                trackers
+                    .get(url)
+                    .is_none_or(|tracker| tracker.enabled != Some(0))
+            };
+            for tier in torrent.trackers.iter_mut() {
+                tier.retain(enabled);
+            }
+            torrent.trackers.retain(|tier| !tier.is_empty());
+            for url in trackers.keys().filter(|url| enabled(url)) {
+                // rTorrent lists DHT as a tracker
+                let known = torrent
+                    .trackers
+                    .iter()
+                    .flatten()
+                    .any(|tracker| tracker == url);
+                if known || url.starts_with("dht://") {
+                    continue;
+                }
+                torrent.trackers.push(vec![url.clone()]);
+            }
+        }
+    }
+
+    torrent.uploaded = session.total_uploaded.unwrap_or_default();
+    torrent.downloaded = session.total_downloaded.unwrap_or_default();
+    torrent.added_time = session
+        .custom
+        .as_ref()
+        .and_then(|custom| custom.get("addtime"))
+        .and_then(|addtime| String::from_utf8_lossy(addtime).trim().parse().ok())
+        .or(session.started)
+        .unwrap_or_default();
+    torrent.completed_time = session.finished.unwrap_or_default();
+    torrent.paused = session.state == Some(0);
+
+    Ok(torrent)
+}
+
+/// Returns the path of a session file next to the .torrent file, e.g. `<hash>.torrent.rtorrent`
+fn with_suffix(torrent_file: &Path, suffix: &str) -> PathBuf {
+    let mut path = torrent_file.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Reads every torrent from an rTorrent session directory
+///
+/// Each `<hash>.torrent` file is paired with the `<hash>.torrent.rtorrent` session state and the
+/// `<hash>.torrent.libtorrent_resume` resume data. The `custom1` label becomes the category. rTorrent
+/// does not track seeding or active time, so those start from zero. Torrents that cannot be read are
+/// reported and skipped.
+pub fn read_rtorrent(directory: &Path) -> Result<Vec<ImportedTorrent>, Box<dyn Error>> {
+    if !directory.is_dir() {
+        return Err(format!("{:?} is not a directory", directory.display()).into());
+    }
+
+    let mut torrent_files: Vec<_> = fs::read_dir(directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    torrent_files.retain(|path| {
+        path.extension()
+            .is_some_and(|extension| extension == "torrent")
+    });
+    torrent_files.sort();
+
+    let mut torrents = Vec::new();
+    for torrent_file in torrent_files {
+        if !with_suffix(&torrent_file, ".rtorrent").is_file() {
+            println!(
+                "Import: skipping {:?}, no .rtorrent session file",
+                torrent_file.display()
+            );
+            continue;
+        }
+        match read_torrent(&torrent_file) {
+            Ok(torrent) => torrents.push(torrent),
+            Err(err) => println!("Import: skipping {:?}, {}", torrent_file.display(), err),
+        }
+    }
+
+    Ok(torrents)
+}
+
+/// Writes torrents to an rTorrent session directory
+///
+/// Each torrent is written as `<HASH>.torrent` along with its `.torrent.rtorrent` session state and
+/// `.torrent.libtorrent_resume` resume data. rTorrent only trusts the completed pieces of files whose
+/// modification time matches, so the times are read from the files on disk, and files that cannot be
+/// found are rechecked. The category becomes the `custom1` label. rTorrent cannot rename individual
+/// files, so torrents with renamed files are reported.
+pub fn write_rtorrent(
+    directory: &Path,
+    torrents: &[ExportedTorrent],
+) -> Result<(), Box<dyn Error>> {
+    for torrent in torrents {
+        let fastresume = &torrent.fastresume;
+        let save_path = QbPath::parse(&fastresume.save_path);
+        let separator = save_path.separator.as_char();
+        let original_paths = torrent.metadata.file_paths();
+
+        // rTorrent saves torrents with multiple files by their root folder
+        let multi_file = torrent.file_paths.iter().all(|path| path.contains('/'));
+        let root = torrent
+            .file_paths
+            .first()
+            .and_then(|path| path.split_once('/'));
+        let download_directory = match (multi_file, root) {
+            (true, Some((root, _))) => format!("{}{}{}", fastresume.save_path, separator, root),
+            _ => fastresume.save_path.clone(),
+        };
+        let renamed_files =
+            torrent
+                .file_paths
+                .iter()
+                .zip(&original_paths)
+                .any(
+                    |(path, original)| match (path.split_once('/'), original.split_once('/')) {
+                        (Some((_, rest)), Some((_, original_rest))) => rest != original_rest,
+                        _ => multi_file && path != original,
+                    },
+                );
+        if renamed_files {
+            println!(
+                "Export: {} has renamed files, rTorrent will look for them at their original paths",
+                torrent.hash
+            );
+        }
+
+        let files = torrent
+            .file_paths
+            .iter()
+            .map(|path| {
+                let file_path = Path::new(&fastresume.save_path).join(path);
+                RtorrentFile {
+                    priority: Some(1),
+                    mtime: fs::metadata(file_path)
+                        .and_then(|metadata| metadata.modified())
+                        .ok()
+                        .and_then(|modified| modified.duration_since(std::time::UNIX_EPOCH).ok())
+                        .map(|modified| modified.as_secs() as i64),
+                }
+            })
+            .collect();
+        let num_complete = torrent.pieces.iter().filter(|have| **have).count();
+        let bitfield = match num_complete == 0 || num_complete == torrent.pieces.len() {
+            true => Value::Int(num_complete as i64),
+            false => Value::Bytes(pack_bitfield(&torrent.pieces)),
+        };
+        let resume = RtorrentResume {
+            bitfield: (!torrent.pieces.is_empty()).then_some(bitfield),
+            uncertain_pieces: None,
+            files: Some(files),
+            trackers: Some(
+                fastresume
+                    .trackers
+                    .iter()
+                    .flatten()
+                    .map(|url| (url.clone(), RtorrentTracker { enabled: Some(1) }))
+                    .collect(),
+            ),
+        };
+
+        let session = RtorrentSession {
+            directory: Some(download_directory),
+            custom1: torrent.category.as_deref().map(encode_label),
+            state: Some((fastresume.paused == 0) as i64),
+            complete: Some(torrent.is_complete() as i64),
+            total_uploaded: Some(fastresume.total_uploaded),
+            total_downloaded: Some(fastresume.total_downloaded),
+            started: Some(fastresume.added_time),
+            finished: Some(fastresume.completed_time),
+            custom: Some(BTreeMap::from([(
+                String::from("addtime"),
+                ByteBuf::from(fastresume.added_time.to_string().into_bytes()),
+            )])),
+        };
+
+        let torrent_file = directory.join(format!("{}.torrent", torrent.hash.to_uppercase()));
+        fs::write(&torrent_file, &torrent.torrent_file)?;
+        fs::write(
+            with_suffix(&torrent_file, ".rtorrent"),
+            serde_bencode::to_bytes(&session)?,
+        )?;
+        fs::write(
+            with_suffix(&torrent_file, ".libtorrent_resume"),
+            serde_bencode::to_bytes(&resume)?,
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_fixtures::{bencode, bytes, dict, info, temp_dir, torrent_file, Version};
+
+    /// Returns resume data with a bitfield and uncertain pieces
+    fn resume(bitfield: Value, uncertain_pieces: &[u32]) -> RtorrentResume {
+        RtorrentResume {
+            bitfield: Some(bitfield),
+            uncertain_pieces: Some(ByteBuf::from(
+                uncertain_pieces
+                    .iter()
+                    .flat_map(|index| index.to_be_bytes())
+                    .collect::<Vec<u8>>(),
+            )),
+            files: None,
+            trackers: None,
+        }
+    }
+
+    #[test]
+    fn reads_resume_bitfield() {
+        let cases = [
+            (resume(Value::Int(10), &[]), vec![true; 10]),
+            (resume(Value::Int(0), &[]), vec![false; 10]),
+            // A count other than all or none cannot be used
+            (resume(Value::Int(4), &[]), Vec::new()),
+            (
+                resume(Value::Bytes(vec![0b1011_0000, 0b0100_0000]), &[]),
+                vec![
+                    true, false, true, true, false, false, false, false, false, true,
+                ],
+            ),
+            // The bitfield must cover every piece exactly
+            (resume(Value::Bytes(vec![0xff]), &[]), Vec::new()),
+            (
+                resume(Value::Int(10), &[1, 9, 42]),
+                vec![true, false, true, true, true, true, true, true, true, false],
+            ),
+        ];
+        for (resume, expected) in cases {
+            assert_eq!(read_pieces(&resume, 10), expected, "{resume:?}");
+        }
+    }
+
+    #[test]
+    fn reads_session_directory() {
+        let directory = temp_dir("rtorrent");
+        let files = [("disc1/a.iso", 32768), ("b.txt", 16384)];
+        let contents = torrent_file(info("Pack", 16384, &files, Version::V1), Vec::new());
+        let torrent_file = directory.join("ABCDEF.torrent");
+        fs::write(&torrent_file, contents).unwrap();
+        let session = dict(vec![
+            ("directory", bytes("/srv/data/Pack (renamed)")),
+            ("custom1", bytes("linux%20isos")),
+            ("state", Value::Int(0)),
+        ]);
+        fs::write(with_suffix(&torrent_file, ".rtorrent"), bencode(&session)).unwrap();
+        let resume = dict(vec![
+            ("bitfield", Value::Bytes(vec![0b1010_0000])),
+            (
+                "uncertain_pieces",
+                Value::Bytes(2u32.to_be_bytes().to_vec()),
+            ),
+        ]);
+        fs::write(
+            with_suffix(&torrent_file, ".libtorrent_resume"),
+            bencode(&resume),
+        )
+        .unwrap();
+
+        let torrents = read_rtorrent(&directory).unwrap();
+        fs::remove_dir_all(&directory).unwrap();
+        assert_eq!(torrents.len(), 1);
+        let torrent = &torrents[0];
+        assert_eq!(torrent.save_path, "/srv/data");
+        assert_eq!(torrent.pieces, vec![true, false, false]);
+        assert_eq!(
+            torrent.mapped_files,
+            Some(vec![
+                String::from("Pack (renamed)/disc1/a.iso"),
+                String::from("Pack (renamed)/b.txt")
+            ])
+        );
+        assert_eq!(torrent.category.as_deref(), Some("linux isos"));
+        assert!(torrent.paused);
+    }
+}
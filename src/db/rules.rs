@@ -0,0 +1,267 @@
+//! Tools for tagging and categorising torrents with rules
+//!
+//! Rules are read from a JSON file containing a list of rules. Each rule has conditions under
+//! `match`, all of which must hold, and the changes to make to matching torrents.
+//!
+//! ```json
+//! [
+//!     { "match": { "tracker_host": "some.tracker" }, "add_tags": ["some.tracker"] },
+//!     { "match": { "save_path": "/media/tv" }, "category": "tv" },
+//!     { "name": "private", "match": { "private": true }, "add_tags": ["private"] }
+//! ]
+//! ```
+
+use crate::db::category::check_category_name;
+use crate::db::filter::{fetch_torrents, split_tags, TorrentFilter};
+use crate::db::tag::join_tags;
+use crate::path::QbPath;
+use rusqlite::{named_params, Connection};
+use serde_derive::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Conditions a torrent must meet for a rule to apply
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct RuleConditions {
+    /// Domain of any of the torrent's trackers, subdomains included
+    pub tracker_host: Option<String>,
+    /// String contained in any of the torrent's tracker URLs
+    pub tracker: Option<String>,
+    /// Path the torrent must be saved in or below
+    pub save_path: Option<String>,
+    /// Whether the torrent must be private or public
+    pub private: Option<bool>,
+    /// Exact category name, an empty string matches torrents without a category
+    pub category: Option<String>,
+    /// Tag the torrent must have
+    pub tag: Option<String>,
+    /// Case-insensitive string contained in the torrent name
+    pub name: Option<String>,
+}
+
+impl RuleConditions {
+    /// Converts the conditions to the equivalent torrent filter
+    fn to_filter(&self) -> TorrentFilter {
+        TorrentFilter {
+            tracker_host: self.tracker_host.clone(),
+            tracker: self.tracker.clone(),
+            save_path: self.save_path.as_deref().map(QbPath::parse),
+            private: self.private,
+            category: self.category.clone(),
+            tag: self.tag.clone(),
+            name: self.name.clone(),
+            ..Default::default()
+        }
+    }
+}
+
+/// A rule assigning a category or tags to matching torrents
+#[derive(Deserialize, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct Rule {
+    /// Rule name used in reports, defaults to the rule's position in the file
+    pub name: Option<String>,
+    /// Conditions a torrent must meet, a rule without conditions matches every torrent
+    #[serde(rename = "match", default)]
+    pub conditions: RuleConditions,
+    /// Category to assign
+    pub category: Option<String>,
+    /// Tags to add
+    #[serde(default)]
+    pub add_tags: Vec<String>,
+    /// Tags to remove
+    #[serde(default)]
+    pub remove_tags: Vec<String>,
+}
+
+/// Rule set information
+#[derive(Debug)]
+pub struct RuleSet {
+    /// Rules in the order they are applied, later rules override the category of earlier ones
+    pub rules: Vec<Rule>,
+    /// Only report what would change without modifying the database
+    pub dry_run: bool,
+}
+
+/// Reads a list of rules from a JSON file
+pub fn load_rules(rules_file: &Path) -> Result<Vec<Rule>, Box<dyn Error>> {
+    let contents = fs::read_to_string(rules_file)?;
+    let rules: Vec<Rule> = serde_json::from_str(&contents)?;
+    // An empty category removes the category instead of naming one
+    for category in rules.iter().filter_map(|rule| rule.category.as_deref()) {
+        if !category.is_empty() {
+            check_category_name(category)?;
+        }
+    }
+    Ok(rules)
+}
+
+/// Applies every rule to the torrents matching the filter, assigning categories and tags
+///
+/// Rules are applied in order, and each rule's conditions are checked against the torrent as earlier
+/// rules left it, so a rule can match a category or tag set by an earlier rule. Tags from every
+/// matching rule are combined, while the category of a later matching rule replaces that of an
+/// earlier one. With `dry_run` set, the changes are reported but the database is not modified.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::rules::{apply_rules, load_rules, RuleSet};
+/// let rule_set = RuleSet {
+///     rules: load_rules(Path::new("rules.json"))?,
+///     dry_run: true,
+/// };
+/// apply_rules(&connection, rule_set, &TorrentFilter::default(), false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the rules that matched each changed torrent.
+pub fn apply_rules(
+    db: &Connection,
+    rule_set: RuleSet,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    match rule_set.dry_run {
+        true => println!("Rules: dry run, the database will not be modified"),
+        false => println!("Rules: applying {} rules", rule_set.rules.len()),
+    }
+
+    let filters: Vec<TorrentFilter> = rule_set
+        .rules
+        .iter()
+        .map(|rule| rule.conditions.to_filter())
+        .collect();
+    let rule_names: Vec<String> = rule_set
+        .rules
+        .iter()
+        .enumerate()
+        .map(|(index, rule)| {
+            rule.name
+                .clone()
+                .unwrap_or_else(|| format!("rule {}", index + 1))
+        })
+        .collect();
+    let mut rule_matches = vec![0; rule_set.rules.len()];
+
+    let torrents = fetch_torrents(db, filter)?;
+
+    let mut num_updated = 0;
+    for mut torrent in torrents {
+        let current_category = torrent.data.category.clone().unwrap_or_default();
+        let current_tags = torrent.tags();
+        let stored_tags = torrent.data.tags.clone();
+
+        let mut category = current_category.clone();
+        let mut tags = current_tags.clone();
+        let mut matched = Vec::new();
+        for (index, rule) in rule_set.rules.iter().enumerate() {
+            if !filters[index].matches(&torrent) {
+                continue;
+            }
+
+            rule_matches[index] += 1;
+            matched.push(rule_names[index].as_str());
+
+            if let Some(rule_category) = &rule.category {
+                category = rule_category.clone();
+            }
+            let remove_tags: Vec<String> = rule
+                .remove_tags
+                .iter()
+                .flat_map(|tag| split_tags(tag))
+                .collect();
+            tags.retain(|tag| !remove_tags.contains(tag));
+            tags.extend(rule.add_tags.iter().flat_map(|tag| split_tags(tag)));
+
+            // Later rules see the changes of earlier ones, so rules can chain
+            torrent.data.category = (!category.is_empty()).then(|| category.clone());
+            torrent.data.tags = join_tags(tags.clone());
+        }
+
+        let new_tags = join_tags(tags);
+        let category_changed = category != current_category;
+        let tags_changed =
+            new_tags.as_deref().unwrap_or_default() != stored_tags.as_deref().unwrap_or_default();
+        if !category_changed && !tags_changed {
+            continue;
+        }
+
+        println!(
+            "Rules: {} ({})",
+            torrent.display_name(),
+            torrent.data.torrent_id
+        );
+        if category_changed {
+            println!(
+                "{}: category {} -> {}",
+                torrent.data.torrent_id,
+                if current_category.is_empty() {
+                    "(none)"
+                } else {
+                    &current_category
+                },
+                if category.is_empty() {
+                    "(none)"
+                } else {
+                    &category
+                }
+            );
+        }
+        if tags_changed {
+            let new_tag_list = split_tags(new_tags.as_deref().unwrap_or_default());
+            let added: Vec<&String> = new_tag_list
+                .iter()
+                .filter(|tag| !current_tags.contains(tag))
+                .collect();
+            let removed: Vec<&String> = current_tags
+                .iter()
+                .filter(|tag| !new_tag_list.contains(tag))
+                .collect();
+            println!(
+                "{}: tags added {:?}, removed {:?}",
+                torrent.data.torrent_id, added, removed
+            );
+        }
+        if verbose {
+            println!(
+                "{}: matched {}",
+                torrent.data.torrent_id,
+                matched.join(", ")
+            );
+        }
+
+        num_updated += 1;
+        if rule_set.dry_run {
+            continue;
+        }
+
+        let category = (!category.is_empty()).then_some(category);
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET category = :category, tags = :tags
+                WHERE id = :id",
+        )?;
+        update_stmt.execute(
+            named_params! {":category": category, ":tags": new_tags, ":id": torrent.data.id},
+        )?;
+    }
+
+    for (name, count) in rule_names.iter().zip(rule_matches) {
+        match count {
+            1 => println!("Rules: {} matched 1 torrent", name),
+            _ => println!("Rules: {} matched {} torrents", name, count),
+        }
+    }
+
+    match (num_updated, rule_set.dry_run) {
+        (0, _) => println!("Rules: no torrents need changes"),
+        (1, true) => println!("Rules: 1 torrent would be updated"),
+        (_, true) => println!("Rules: {} torrents would be updated", num_updated),
+        (1, false) => println!("Rules: 1 torrent was updated"),
+        (_, false) => println!("Rules: {} torrents were updated", num_updated),
+    }
+
+    Ok(())
+}
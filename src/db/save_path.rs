@@ -1,6 +1,7 @@
 //! Tools for modifying torrent save path
 
 use crate::db::db_structs::{FastresumeData, FetchedPathData};
+use crate::path::{is_windows_style, QbPath, Separator};
 use rusqlite::{named_params, Connection};
 use serde_rusqlite::from_rows;
 use std::error::Error;
@@ -21,12 +22,45 @@ pub struct SavePath {
     /// New save path string, OS-specific
     pub new: String,
     /// Separator to use in file paths, should default to current OS style
-    pub separator: String,
+    pub separator: Separator,
+    /// Also apply the replacement to the `download_path` column
+    pub include_download_path: bool,
+}
+
+impl SavePath {
+    /// Applies the replacement to a path stored with Unix-style separators
+    ///
+    /// Returns `None` if the path does not contain the old path, so it is left exactly as it is.
+    fn replace_portable(&self, path: &str) -> Option<String> {
+        path.contains(&self.old_unix).then(|| {
+            QbPath::parse_portable(&path.replace(&self.old_unix, &self.new_unix)).to_portable()
+        })
+    }
+
+    /// Applies the replacement to a path stored with OS-specific separators, then converts it to
+    /// the configured separator
+    ///
+    /// Returns `None` if the path does not contain the old path, so it is left exactly as it is.
+    fn replace_native(&self, path: &str) -> Option<String> {
+        if !path.contains(&self.old) {
+            return None;
+        }
+        // A Windows path keeps its Windows separators even if the replacement mixes in slashes
+        let replaced = path.replace(&self.old, &self.new);
+        let new_path = if is_windows_style(path) {
+            QbPath::parse_windows(&replaced)
+        } else {
+            QbPath::parse(&replaced)
+        };
+        Some(new_path.to_native(self.separator))
+    }
 }
 
 /// Performs a string replace operation on two database columns where qB stores the
 /// save path information: `target_save_path` and `libtorrent_resume_data`.
 ///
+/// If `include_download_path` is set, the `download_path` column is updated as well.
+///
 /// ## Example
 /// ```rs
 /// use qbfrt::db::save_path::{change_save_path, SavePath};
@@ -35,7 +69,8 @@ pub struct SavePath {
 ///     new_unix: String::from("/new/test/dir"),
 ///     old: String::from("\\old\\save\\path"),
 ///     new: String::from("\\new\\test\\dir"),
-///     separator: '\\'.to_string(),
+///     separator: Separator::Windows,
+///     include_download_path: false,
 /// };
 /// change_save_path(&connection, save_path, false);
 /// ```
@@ -47,14 +82,14 @@ pub struct SavePath {
 /// ## Troubleshooting
 /// ### Save path was updated but qB says the torrent files are missing
 /// > Make sure your replacement string was correct. Open the database with something such as
-/// [Beekeeper Studio](https://www.beekeeperstudio.io/) and look at the target_save_path column
-/// in the torrents table. You can use an online hex to string converter to look at the
-/// libtorrent_resume_data save path. It should be the same as the target_save_path field.
+/// > [Beekeeper Studio](https://www.beekeeperstudio.io/) and look at the target_save_path column
+/// > in the torrents table. You can use an online hex to string converter to look at the
+/// > libtorrent_resume_data save path. It should be the same as the target_save_path field.
 ///
 /// ### The target_save_path changed but libtorrent_resume_data did not
 /// > Restore the old database and re-run the command with verbose output enabled. Make sure that
-/// the target_save_path and libtorrent_resume_data have the exact same path separators for the new
-/// string. If they are different, you likely used the incorrect path separators in the old string.
+/// > the target_save_path and libtorrent_resume_data have the exact same path separators for the new
+/// > string. If they are different, you likely used the incorrect path separators in the old string.
 pub fn change_save_path(
     db: &Connection,
     save_path: SavePath,
@@ -65,24 +100,38 @@ pub fn change_save_path(
         save_path.old, save_path.new
     );
 
-    let search_query = format!(
-        "SELECT id, torrent_id, target_save_path, libtorrent_resume_data 
-            FROM torrents 
-            WHERE target_save_path LIKE '%{0}%'",
-        &save_path.old_unix
+    let search_query = if save_path.include_download_path {
+        "SELECT id, torrent_id, target_save_path, download_path, libtorrent_resume_data
+            FROM torrents
+            WHERE target_save_path LIKE '%' || :old || '%' OR download_path LIKE '%' || :old || '%'"
+    } else {
+        "SELECT id, torrent_id, target_save_path, download_path, libtorrent_resume_data
+            FROM torrents
+            WHERE target_save_path LIKE '%' || :old || '%'"
+    };
+    let mut search_stmt = db.prepare(search_query)?;
+    let relevant_rows = from_rows::<FetchedPathData>(
+        search_stmt.query(named_params! {":old": save_path.old_unix})?,
     );
-    let mut search_stmt = db.prepare(&search_query)?;
-    let relevant_rows = from_rows::<FetchedPathData>(search_stmt.query([])?);
 
     let mut num_updated = 0;
     for row in relevant_rows {
         let torrent = row?;
 
         // qB always stores the "target_save_path" with Unix-style separators, even on Windows
-        let target_save_path = torrent
+        let new_target_save_path = torrent
             .target_save_path
-            .unwrap()
-            .replace(&save_path.old_unix, &save_path.new_unix);
+            .as_deref()
+            .and_then(|path| save_path.replace_portable(path));
+        let target_changed = new_target_save_path.is_some();
+        let target_save_path = new_target_save_path.or(torrent.target_save_path);
+        let new_download_path = torrent
+            .download_path
+            .as_deref()
+            .filter(|_| save_path.include_download_path)
+            .and_then(|path| save_path.replace_portable(path));
+        let download_changed = new_download_path.is_some();
+        let download_path = new_download_path.or(torrent.download_path);
 
         let bencoded_data = torrent.libtorrent_resume_data.as_slice();
         let mut libtorrent_resume_data: FastresumeData = serde_bencode::from_bytes(bencoded_data)?;
@@ -90,34 +139,40 @@ pub fn change_save_path(
         // In the libtorrent data, qB uses OS-specific separators. It is up to the end user to make
         // sure their path strings use the appropriate separator for matching. However, we do allow
         // conversion to and from Windows- and Unix-style separators after the replacement.
-        if save_path.separator == *"\\" {
-            libtorrent_resume_data.save_path = libtorrent_resume_data
-                .save_path
-                .replace(&save_path.old, &save_path.new)
-                .replace('/', &save_path.separator);
-        } else {
-            libtorrent_resume_data.save_path = libtorrent_resume_data
-                .save_path
-                .replace(&save_path.old, &save_path.new)
-                .replace('\\', &save_path.separator);
+        let new_save_path = save_path.replace_native(&libtorrent_resume_data.save_path);
+        let save_path_changed = new_save_path.is_some();
+        if let Some(new_save_path) = new_save_path {
+            libtorrent_resume_data.save_path = new_save_path;
+        }
+
+        // Paths that do not contain the old path are left exactly as they are
+        if !(target_changed || download_changed || save_path_changed) {
+            continue;
         }
 
         let mut update_stmt = db.prepare(
             "UPDATE torrents
-                SET target_save_path = :tsp, libtorrent_resume_data = :lrd
+                SET target_save_path = :tsp, download_path = :dp, libtorrent_resume_data = :lrd
                 WHERE id = :id
                 RETURNING torrent_id;",
         )?;
         update_stmt
                 .query_row(
-                    named_params! {":tsp": target_save_path, ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?, ":id": torrent.id},
+                    named_params! {":tsp": target_save_path, ":dp": download_path, ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?, ":id": torrent.id},
                     |row| {
                         let updated_row_id = row.get::<usize, String>(0)?;
 
                         if verbose {
                             println!("Save path: updated save path for {}", updated_row_id);
-                            println!("{}: new target_save_path is {}", updated_row_id, target_save_path);
-                            println!("{}: new libtorrent_resume_data path is {}", updated_row_id, libtorrent_resume_data.save_path);
+                            if let Some(target_save_path) = target_save_path.as_ref().filter(|_| target_changed) {
+                                println!("{}: new target_save_path is {}", updated_row_id, target_save_path);
+                            }
+                            if let Some(download_path) = download_path.as_ref().filter(|_| download_changed) {
+                                println!("{}: new download_path is {}", updated_row_id, download_path);
+                            }
+                            if save_path_changed {
+                                println!("{}: new libtorrent_resume_data path is {}", updated_row_id, libtorrent_resume_data.save_path);
+                            }
                         }
 
                         num_updated += 1;
@@ -135,3 +190,227 @@ pub fn change_save_path(
 
     Ok(())
 }
+
+/// Performs a string replace operation on the `download_path` column, where qB stores the
+/// incomplete download directory.
+///
+/// While a torrent is downloading to its download path, the save_path within `libtorrent_resume_data`
+/// points to the download path rather than the save path. For those torrents the fastresume save_path
+/// is updated as well.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::save_path::{change_download_path, SavePath};
+/// let download_path = SavePath {
+///     old_unix: String::from("D:/Incomplete"),
+///     new_unix: String::from("/mnt/incomplete"),
+///     old: String::from("D:\\Incomplete"),
+///     new: String::from("/mnt/incomplete"),
+///     separator: Separator::Unix,
+///     include_download_path: true,
+/// };
+/// change_download_path(&connection, download_path, false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the torrent hash, the new download_path, and the new
+/// save_path within the libtorrent_resume_data blob if it changed.
+pub fn change_download_path(
+    db: &Connection,
+    download_path: SavePath,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    println!(
+        "Download path: replacing {} with {}",
+        download_path.old, download_path.new
+    );
+
+    let mut search_stmt = db.prepare(
+        "SELECT id, torrent_id, target_save_path, download_path, libtorrent_resume_data
+            FROM torrents
+            WHERE download_path LIKE '%' || :old || '%'",
+    )?;
+    let relevant_rows = from_rows::<FetchedPathData>(
+        search_stmt.query(named_params! {":old": download_path.old_unix})?,
+    );
+
+    let mut num_updated = 0;
+    for row in relevant_rows {
+        let torrent = row?;
+        let old_download_path = torrent.download_path.unwrap();
+        // The search is case-insensitive, the replacement is not
+        let Some(new_download_path) = download_path.replace_portable(&old_download_path) else {
+            continue;
+        };
+
+        let bencoded_data = torrent.libtorrent_resume_data.as_slice();
+        let mut libtorrent_resume_data: FastresumeData = serde_bencode::from_bytes(bencoded_data)?;
+
+        let new_save_path = save_paths_match(&old_download_path, &libtorrent_resume_data.save_path)
+            .then(|| download_path.replace_native(&libtorrent_resume_data.save_path))
+            .flatten();
+        let save_path_changed = new_save_path.is_some();
+        if let Some(new_save_path) = new_save_path {
+            libtorrent_resume_data.save_path = new_save_path;
+        }
+
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET download_path = :dp, libtorrent_resume_data = :lrd
+                WHERE id = :id
+                RETURNING torrent_id;",
+        )?;
+        update_stmt.query_row(
+            named_params! {":dp": new_download_path, ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?, ":id": torrent.id},
+            |row| {
+                let updated_row_id = row.get::<usize, String>(0)?;
+
+                if verbose {
+                    println!("Download path: updated download path for {}", updated_row_id);
+                    println!("{}: new download_path is {}", updated_row_id, new_download_path);
+                    if save_path_changed {
+                        println!("{}: new libtorrent_resume_data path is {}", updated_row_id, libtorrent_resume_data.save_path);
+                    }
+                }
+
+                num_updated += 1;
+
+                Ok(())
+            },
+        )?;
+    }
+
+    match num_updated {
+        0 => println!("Download path: no torrents were updated"),
+        1 => println!("Download path: 1 torrent was updated"),
+        _ => println!("Download path: {} torrents were updated", num_updated),
+    }
+
+    Ok(())
+}
+
+/// Which of the two save path locations is treated as correct during a repair
+#[derive(Debug)]
+pub enum RepairSource {
+    /// Rewrite the fastresume save_path from `target_save_path`
+    TargetSavePath,
+    /// Rewrite `target_save_path` from the fastresume save_path
+    Fastresume,
+}
+
+/// Save path repair information
+#[derive(Debug)]
+pub struct SavePathRepair {
+    /// The save path location treated as correct
+    pub source: RepairSource,
+    /// Separator to use when rewriting the fastresume save_path, should default to current OS style
+    pub separator: Separator,
+}
+
+/// Compares a Unix-style `target_save_path` with an OS-specific fastresume `save_path`, ignoring
+/// separator style and trailing separators
+pub fn save_paths_match(target_save_path: &str, save_path: &str) -> bool {
+    QbPath::parse_portable(target_save_path).same_path(&QbPath::parse(save_path))
+}
+
+/// Finds torrents where `target_save_path` and the save_path within `libtorrent_resume_data`
+/// disagree, and rewrites one from the other.
+///
+/// Torrents with an empty `target_save_path` use automatic torrent management and are skipped, as are
+/// incomplete torrents whose fastresume save_path points to their `download_path`.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::save_path::{repair_save_path, RepairSource, SavePathRepair};
+/// let repair = SavePathRepair {
+///     source: RepairSource::TargetSavePath,
+///     separator: Separator::Windows,
+/// };
+/// repair_save_path(&connection, repair, false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the torrent hash and both save paths for every
+/// repaired torrent.
+pub fn repair_save_path(
+    db: &Connection,
+    repair: SavePathRepair,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    match repair.source {
+        RepairSource::TargetSavePath => {
+            println!("Repair: rewriting libtorrent_resume_data save paths from target_save_path")
+        }
+        RepairSource::Fastresume => {
+            println!("Repair: rewriting target_save_path from libtorrent_resume_data save paths")
+        }
+    }
+
+    let mut search_stmt = db.prepare(
+        "SELECT id, torrent_id, target_save_path, download_path, libtorrent_resume_data
+        FROM torrents
+        WHERE target_save_path IS NOT NULL AND target_save_path != ''",
+    )?;
+    let relevant_rows = from_rows::<FetchedPathData>(search_stmt.query([])?);
+
+    let mut num_updated = 0;
+    for row in relevant_rows {
+        let torrent = row?;
+        let mut target_save_path = torrent.target_save_path.unwrap();
+
+        let bencoded_data = torrent.libtorrent_resume_data.as_slice();
+        let mut libtorrent_resume_data: FastresumeData = serde_bencode::from_bytes(bencoded_data)?;
+
+        // Incomplete torrents are saved to their download path until they finish
+        let downloading_to_download_path = torrent
+            .download_path
+            .is_some_and(|path| save_paths_match(&path, &libtorrent_resume_data.save_path));
+        if downloading_to_download_path
+            || save_paths_match(&target_save_path, &libtorrent_resume_data.save_path)
+        {
+            continue;
+        }
+
+        match repair.source {
+            RepairSource::TargetSavePath => {
+                libtorrent_resume_data.save_path =
+                    QbPath::parse_portable(&target_save_path).to_native(repair.separator);
+            }
+            RepairSource::Fastresume => {
+                // qB always stores the "target_save_path" with Unix-style separators, even on Windows
+                target_save_path = QbPath::parse(&libtorrent_resume_data.save_path).to_portable();
+            }
+        }
+
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET target_save_path = :tsp, libtorrent_resume_data = :lrd
+                WHERE id = :id
+                RETURNING torrent_id;",
+        )?;
+        update_stmt.query_row(
+            named_params! {":tsp": target_save_path, ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?, ":id": torrent.id},
+            |row| {
+                let updated_row_id = row.get::<usize, String>(0)?;
+
+                if verbose {
+                    println!("Repair: repaired save path for {}", updated_row_id);
+                    println!("{}: target_save_path is {}", updated_row_id, target_save_path);
+                    println!("{}: libtorrent_resume_data path is {}", updated_row_id, libtorrent_resume_data.save_path);
+                }
+
+                num_updated += 1;
+
+                Ok(())
+            },
+        )?;
+    }
+
+    match num_updated {
+        0 => println!("Repair: no torrents needed repair"),
+        1 => println!("Repair: 1 torrent was repaired"),
+        _ => println!("Repair: {} torrents were repaired", num_updated),
+    }
+
+    Ok(())
+}
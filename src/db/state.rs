@@ -0,0 +1,254 @@
+//! Tools for starting and stopping torrents and switching their management mode
+
+use crate::db::filter::{fetch_torrents, TorrentFilter};
+use crate::path::QbPath;
+use rusqlite::{named_params, Connection};
+use std::error::Error;
+
+/// Whether a torrent runs, and whether qB's queueing applies to it
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum RunState {
+    /// Running and queued normally
+    Started,
+    /// Running and ignoring queue limits, "Force Resume" in qB
+    Forced,
+    /// Stopped, "Paused" in older qB versions
+    Stopped,
+}
+
+impl RunState {
+    /// Parses a run state name: 'started', 'forced' or 'stopped'
+    pub fn parse(state: &str) -> Result<RunState, String> {
+        match state {
+            "started" => Ok(RunState::Started),
+            "forced" => Ok(RunState::Forced),
+            "stopped" => Ok(RunState::Stopped),
+            _ => Err(format!(
+                "{state} is not a valid state, use 'started', 'forced' or 'stopped'"
+            )),
+        }
+    }
+}
+
+/// How a torrent's save path is chosen
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ManagementMode {
+    /// Automatic Torrent Management, the save path follows the torrent's category
+    Automatic,
+    /// The save path is set on the torrent itself
+    Manual,
+}
+
+impl ManagementMode {
+    /// Parses a management mode name: 'auto' or 'manual'
+    pub fn parse(mode: &str) -> Result<ManagementMode, String> {
+        match mode {
+            "auto" => Ok(ManagementMode::Automatic),
+            "manual" => Ok(ManagementMode::Manual),
+            _ => Err(format!(
+                "{mode} is not a valid management mode, use 'auto' or 'manual'"
+            )),
+        }
+    }
+}
+
+/// Parses a stop condition, 'none', 'metadata' or 'checked', into the value qB stores
+pub fn parse_stop_condition(condition: &str) -> Result<String, String> {
+    match condition {
+        "none" => Ok(String::from("None")),
+        "metadata" => Ok(String::from("MetadataReceived")),
+        "checked" => Ok(String::from("FilesChecked")),
+        _ => Err(format!(
+            "{condition} is not a valid stop condition, use 'none', 'metadata' or 'checked'"
+        )),
+    }
+}
+
+/// State changes to make to torrents, unset fields are left unchanged
+#[derive(Debug, Default)]
+pub struct StateChange {
+    /// New run state
+    pub run_state: Option<RunState>,
+    /// New management mode
+    pub management_mode: Option<ManagementMode>,
+    /// New stop condition, as stored by qB
+    pub stop_condition: Option<String>,
+}
+
+impl StateChange {
+    /// Returns true if no changes are set
+    pub fn is_empty(&self) -> bool {
+        self.run_state.is_none() && self.management_mode.is_none() && self.stop_condition.is_none()
+    }
+}
+
+/// Starts or stops torrents and changes their management mode and stop condition
+///
+/// The run state is stored in the `stopped` and `operating_mode` columns, and is mirrored into the
+/// `paused` and `auto_managed` flags of `libtorrent_resume_data` the way qB stores them. A started
+/// torrent in the download queue keeps its `paused` flag, so libtorrent's queueing decides whether it
+/// runs. The flags are only changed along with the run state. Automatic Torrent
+/// Management is marked by an empty `target_save_path`, so switching to it clears the save and
+/// download paths, while switching to manual mode sets `target_save_path` from the fastresume save
+/// path. Incomplete torrents are left in automatic mode, as their fastresume save path may be the
+/// download path.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::state::{change_state, RunState, StateChange};
+/// let change = StateChange {
+///     run_state: Some(RunState::Stopped),
+///     ..Default::default()
+/// };
+/// change_state(&connection, change, &TorrentFilter::default(), false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the torrent hash and new state for every updated
+/// torrent.
+pub fn change_state(
+    db: &Connection,
+    change: StateChange,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    println!("State: updating torrent state");
+
+    let torrents = fetch_torrents(db, filter)?;
+
+    let mut num_updated = 0;
+    let mut num_skipped = 0;
+    for torrent in torrents {
+        let mut data = torrent.data;
+        let mut libtorrent_resume_data = torrent.fastresume;
+
+        let before = (
+            data.stopped,
+            data.operating_mode.clone(),
+            data.stop_condition.clone(),
+            data.target_save_path.clone(),
+            data.download_path.clone(),
+            libtorrent_resume_data.paused,
+            libtorrent_resume_data.auto_managed,
+        );
+
+        if let Some(run_state) = change.run_state {
+            data.stopped = (run_state == RunState::Stopped) as i64;
+            data.operating_mode = match run_state {
+                RunState::Forced => String::from("Forced"),
+                _ => String::from("AutoManaged"),
+            };
+            // libtorrent only auto manages running torrents that are not forced
+            let (paused, auto_managed) = match run_state {
+                RunState::Stopped => (1, 0),
+                RunState::Started if data.queue_position >= 0 => (libtorrent_resume_data.paused, 1),
+                RunState::Started => (0, 1),
+                RunState::Forced => (0, 0),
+            };
+            libtorrent_resume_data.paused = paused;
+            libtorrent_resume_data.auto_managed = auto_managed;
+        }
+
+        if let Some(stop_condition) = &change.stop_condition {
+            data.stop_condition = stop_condition.clone();
+        }
+
+        let is_automatic = data
+            .target_save_path
+            .as_deref()
+            .unwrap_or_default()
+            .is_empty();
+        match change.management_mode {
+            Some(ManagementMode::Automatic) if !is_automatic => {
+                data.target_save_path = Some(String::new());
+                data.download_path = Some(String::new());
+            }
+            Some(ManagementMode::Manual) if is_automatic => {
+                if data.has_seed_status == 0 {
+                    println!(
+                        "State: skipping manual mode for incomplete torrent {}, its save path is unknown",
+                        data.torrent_id
+                    );
+                    num_skipped += 1;
+                } else {
+                    data.target_save_path =
+                        Some(QbPath::parse(&libtorrent_resume_data.save_path).to_portable());
+                }
+            }
+            _ => {}
+        }
+
+        let after = (
+            data.stopped,
+            data.operating_mode.clone(),
+            data.stop_condition.clone(),
+            data.target_save_path.clone(),
+            data.download_path.clone(),
+            libtorrent_resume_data.paused,
+            libtorrent_resume_data.auto_managed,
+        );
+        if before == after {
+            continue;
+        }
+
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET stopped = :stopped, operating_mode = :om, stop_condition = :sc,
+                    target_save_path = :tsp, download_path = :dp, libtorrent_resume_data = :lrd
+                WHERE id = :id
+                RETURNING torrent_id;",
+        )?;
+        update_stmt.query_row(
+            named_params! {
+                ":stopped": data.stopped,
+                ":om": data.operating_mode,
+                ":sc": data.stop_condition,
+                ":tsp": data.target_save_path,
+                ":dp": data.download_path,
+                ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?,
+                ":id": data.id,
+            },
+            |row| {
+                let updated_row_id = row.get::<usize, String>(0)?;
+
+                if verbose {
+                    let run_state = match (data.stopped, data.operating_mode.as_str()) {
+                        (0, "Forced") => "forced",
+                        (0, _) => "started",
+                        _ => "stopped",
+                    };
+                    let management_mode = match data.target_save_path.as_deref().unwrap_or_default()
+                    {
+                        "" => "auto",
+                        _ => "manual",
+                    };
+                    println!("State: updated state for {}", updated_row_id);
+                    println!(
+                        "{}: {}, {} management, stop condition {}",
+                        updated_row_id, run_state, management_mode, data.stop_condition
+                    );
+                }
+
+                num_updated += 1;
+
+                Ok(())
+            },
+        )?;
+    }
+
+    match num_skipped {
+        0 => {}
+        1 => println!("State: 1 incomplete torrent was left in automatic mode"),
+        _ => println!(
+            "State: {} incomplete torrents were left in automatic mode",
+            num_skipped
+        ),
+    }
+    match num_updated {
+        0 => println!("State: no torrents were updated"),
+        1 => println!("State: 1 torrent was updated"),
+        _ => println!("State: {} torrents were updated", num_updated),
+    }
+
+    Ok(())
+}
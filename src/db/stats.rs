@@ -0,0 +1,235 @@
+//! Tools for summarising transfer statistics across torrents
+
+use crate::db::filter::{fetch_torrents, Torrent, TorrentFilter};
+use crate::db::tracker_report::format_size;
+use crate::db::tracker_url::tracker_host;
+use rusqlite::Connection;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+
+/// Age buckets for the added and completed time distributions, in days
+const AGE_BUCKETS: [(i64, &str); 6] = [
+    (7, "under a week ago"),
+    (30, "under a month ago"),
+    (182, "under 6 months ago"),
+    (365, "under a year ago"),
+    (730, "under 2 years ago"),
+    (i64::MAX, "2 years ago or more"),
+];
+
+/// Totals for a group of torrents
+#[derive(Debug, Default)]
+struct Totals {
+    /// Number of torrents
+    torrents: usize,
+    /// Total size in bytes
+    size: u64,
+    /// Total uploaded in bytes
+    uploaded: u64,
+    /// Total downloaded in bytes
+    downloaded: u64,
+    /// Total seeding time in seconds
+    seeding_time: u64,
+    /// Total active time in seconds
+    active_time: u64,
+}
+
+impl Totals {
+    /// Adds a torrent to the totals
+    fn add(&mut self, torrent: &Torrent) {
+        self.torrents += 1;
+        self.size += torrent.total_size().max(0) as u64;
+        self.uploaded += torrent.fastresume.total_uploaded;
+        self.downloaded += torrent.fastresume.total_downloaded;
+        self.seeding_time += torrent.fastresume.seeding_time;
+        self.active_time += torrent.fastresume.active_time.max(0) as u64;
+    }
+
+    /// Returns the share ratio, measured against the size when little was downloaded like qB does
+    fn ratio(&self) -> String {
+        let downloaded = match self.downloaded < self.size / 100 {
+            true => self.size,
+            false => self.downloaded,
+        };
+        match (self.uploaded, downloaded) {
+            (0, 0) => String::from("0.00"),
+            (_, 0) => String::from("inf"),
+            (uploaded, downloaded) => format!("{:.2}", uploaded as f64 / downloaded as f64),
+        }
+    }
+
+    /// Formats the totals as a single report line
+    fn describe(&self) -> String {
+        format!(
+            "{} {}, {}, uploaded {}, downloaded {}, ratio {}, seeding {}, active {}",
+            self.torrents,
+            if self.torrents == 1 {
+                "torrent"
+            } else {
+                "torrents"
+            },
+            format_size(self.size),
+            format_size(self.uploaded),
+            format_size(self.downloaded),
+            self.ratio(),
+            format_duration(self.seeding_time),
+            format_duration(self.active_time)
+        )
+    }
+}
+
+/// Formats a number of seconds as days, hours and minutes, e.g. `3d 4h 5m`
+pub fn format_duration(seconds: u64) -> String {
+    let minutes = seconds / 60;
+    match (minutes / (60 * 24), minutes / 60 % 24, minutes % 60) {
+        (0, 0, minutes) => format!("{minutes}m"),
+        (0, hours, minutes) => format!("{hours}h {minutes}m"),
+        (days, hours, minutes) => format!("{days}d {hours}h {minutes}m"),
+    }
+}
+
+/// Returns the label of the age bucket a timestamp falls in
+fn age_bucket(now: i64, timestamp: i64) -> &'static str {
+    let age_days = (now - timestamp).max(0) / (60 * 60 * 24);
+    AGE_BUCKETS
+        .iter()
+        .find(|(max_days, _)| age_days < *max_days)
+        .map_or("2 years ago or more", |(_, label)| label)
+}
+
+/// Prints how many timestamps fall in each age bucket
+fn print_distribution(title: &str, counts: &BTreeMap<&str, usize>) {
+    let distribution: Vec<String> = AGE_BUCKETS
+        .iter()
+        .filter_map(|(_, label)| {
+            counts
+                .get(label)
+                .map(|count| format!("{}: {}", label, count))
+        })
+        .collect();
+    match distribution.is_empty() {
+        true => println!("Stats: {} none", title),
+        false => println!("Stats: {} {}", title, distribution.join(", ")),
+    }
+}
+
+/// Summarises transfer statistics of the torrents matching the filter
+///
+/// Shows the total size, uploaded and downloaded amounts, ratio, seeding time and active time, first
+/// for all selected torrents and then per category and per tracker host. A torrent is counted under
+/// every tracker host it announces to. It also shows how long ago torrents were added and completed,
+/// and lists torrents that have never uploaded anything. Nothing is modified.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::stats::show_stats;
+/// show_stats(&connection, &TorrentFilter::default(), false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will also output the totals of every torrent.
+pub fn show_stats(
+    db: &Connection,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    println!("Stats: summarising torrents");
+
+    let torrents = fetch_torrents(db, filter)?;
+    let now = chrono::offset::Utc::now().timestamp();
+
+    let mut totals = Totals::default();
+    let mut categories: BTreeMap<String, Totals> = BTreeMap::new();
+    let mut hosts: BTreeMap<String, Totals> = BTreeMap::new();
+    let mut added: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut completed: BTreeMap<&str, usize> = BTreeMap::new();
+    let mut num_incomplete = 0;
+    let mut never_uploaded = Vec::new();
+    for torrent in &torrents {
+        totals.add(torrent);
+
+        let category = match torrent.data.category.as_deref().unwrap_or_default() {
+            "" => String::from("(none)"),
+            category => category.to_string(),
+        };
+        categories.entry(category).or_default().add(torrent);
+
+        let torrent_hosts: BTreeSet<String> = torrent
+            .fastresume
+            .trackers
+            .iter()
+            .flatten()
+            .filter_map(|url| tracker_host(url))
+            .collect();
+        match torrent_hosts.is_empty() {
+            true => hosts
+                .entry(String::from("(none)"))
+                .or_default()
+                .add(torrent),
+            false => {
+                for host in torrent_hosts {
+                    hosts.entry(host).or_default().add(torrent);
+                }
+            }
+        }
+
+        *added
+            .entry(age_bucket(now, torrent.fastresume.added_time))
+            .or_default() += 1;
+        match torrent.fastresume.completed_time {
+            time if time > 0 => *completed.entry(age_bucket(now, time)).or_default() += 1,
+            _ => num_incomplete += 1,
+        }
+
+        if torrent.fastresume.total_uploaded == 0 {
+            never_uploaded.push(torrent);
+        }
+
+        if verbose {
+            let mut torrent_totals = Totals::default();
+            torrent_totals.add(torrent);
+            println!(
+                "{}: {}, {}",
+                torrent.data.torrent_id,
+                torrent.display_name(),
+                torrent_totals.describe()
+            );
+        }
+    }
+
+    println!("Stats: total {}", totals.describe());
+    for (category, category_totals) in &categories {
+        println!(
+            "Stats: category {}: {}",
+            category,
+            category_totals.describe()
+        );
+    }
+    for (host, host_totals) in &hosts {
+        println!("Stats: tracker {}: {}", host, host_totals.describe());
+    }
+
+    print_distribution("added", &added);
+    print_distribution("completed", &completed);
+    match num_incomplete {
+        0 => {}
+        1 => println!("Stats: 1 torrent has not completed"),
+        _ => println!("Stats: {} torrents have not completed", num_incomplete),
+    }
+
+    for torrent in &never_uploaded {
+        println!(
+            "Stats: never uploaded {} ({}), added {}",
+            torrent.display_name(),
+            torrent.data.torrent_id,
+            age_bucket(now, torrent.fastresume.added_time)
+        );
+    }
+    match never_uploaded.len() {
+        0 => println!("Stats: every torrent has uploaded"),
+        1 => println!("Stats: 1 torrent has never uploaded"),
+        count => println!("Stats: {} torrents have never uploaded", count),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,134 @@
+//! Tools for managing torrent tags
+
+use crate::db::filter::{fetch_torrents, split_tags, TorrentFilter};
+use rusqlite::{named_params, Connection};
+use std::error::Error;
+
+/// Tag operation to perform
+#[derive(Debug)]
+pub enum TagOperation {
+    /// Add tags to the selected torrents
+    Add(Vec<String>),
+    /// Remove tags from the selected torrents
+    Remove(Vec<String>),
+    /// Rename a tag on the selected torrents
+    Rename {
+        /// Existing tag name
+        old: String,
+        /// New tag name
+        new: String,
+    },
+    /// Replace the tags of the selected torrents
+    Set(Vec<String>),
+}
+
+/// Joins tags into the comma-separated form qB stores, sorted and without duplicates
+///
+/// Returns `None` if there are no tags.
+pub fn join_tags(mut tags: Vec<String>) -> Option<String> {
+    tags.sort_by(|a, b| a.to_lowercase().cmp(&b.to_lowercase()).then(a.cmp(b)));
+    tags.dedup();
+
+    match tags.is_empty() {
+        true => None,
+        false => Some(tags.join(",")),
+    }
+}
+
+/// Adds, removes, renames or replaces the tags of every torrent matching the filter
+///
+/// Tags are trimmed of whitespace, and each torrent ends up with a sorted tag list without duplicates.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::tag::{change_tags, TagOperation};
+/// let filter = TorrentFilter {
+///     tracker: Some(String::from("some.tracker")),
+///     ..Default::default()
+/// };
+/// change_tags(&connection, TagOperation::Add(vec![String::from("some")]), &filter, false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the torrent hash and new tags for every updated
+/// torrent.
+pub fn change_tags(
+    db: &Connection,
+    operation: TagOperation,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    match &operation {
+        TagOperation::Add(tags) => println!("Tags: adding {}", tags.join(", ")),
+        TagOperation::Remove(tags) => println!("Tags: removing {}", tags.join(", ")),
+        TagOperation::Rename { old, new } => println!("Tags: renaming {} to {}", old, new),
+        TagOperation::Set(tags) => println!("Tags: setting tags to {}", tags.join(", ")),
+    }
+
+    let torrents = fetch_torrents(db, filter)?;
+
+    let mut num_updated = 0;
+    for torrent in torrents {
+        let current = torrent.tags();
+        let updated = match &operation {
+            TagOperation::Add(tags) => current.iter().chain(tags).cloned().collect(),
+            TagOperation::Remove(tags) => current
+                .iter()
+                .filter(|tag| !tags.contains(tag))
+                .cloned()
+                .collect(),
+            TagOperation::Rename { old, new } => current
+                .iter()
+                .map(|tag| match tag == old {
+                    true => new.clone(),
+                    false => tag.clone(),
+                })
+                .collect(),
+            TagOperation::Set(tags) => tags.clone(),
+        };
+
+        let tags = join_tags(updated);
+        if tags.as_deref().unwrap_or_default() == torrent.data.tags.as_deref().unwrap_or_default() {
+            continue;
+        }
+
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET tags = :tags
+                WHERE id = :id
+                RETURNING torrent_id;",
+        )?;
+        update_stmt.query_row(
+            named_params! {":tags": tags, ":id": torrent.data.id},
+            |row| {
+                let updated_row_id = row.get::<usize, String>(0)?;
+
+                if verbose {
+                    println!("Tags: updated tags for {}", updated_row_id);
+                    println!(
+                        "{}: new tags are {}",
+                        updated_row_id,
+                        tags.as_deref().unwrap_or("(none)")
+                    );
+                }
+
+                num_updated += 1;
+
+                Ok(())
+            },
+        )?;
+    }
+
+    match num_updated {
+        0 => println!("Tags: no torrents were updated"),
+        1 => println!("Tags: 1 torrent was updated"),
+        _ => println!("Tags: {} torrents were updated", num_updated),
+    }
+
+    Ok(())
+}
+
+/// Splits tag arguments, which may themselves be comma-separated, into individual tags
+pub fn parse_tag_args(args: &[String]) -> Vec<String> {
+    args.iter().flat_map(|arg| split_tags(arg)).collect()
+}
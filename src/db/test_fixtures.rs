@@ -0,0 +1,128 @@
+//! Fixtures for unit tests, .torrent files, resume data and databases generated on the fly
+
+use rusqlite::Connection;
+use serde_bencode::value::Value;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+use std::process;
+
+/// Schema of the torrents table in qB's torrents.db
+const TORRENTS_TABLE: &str = "CREATE TABLE torrents (id INTEGER PRIMARY KEY,
+    torrent_id BLOB NOT NULL UNIQUE, queue_position INTEGER NOT NULL DEFAULT -1, name TEXT,
+    category TEXT, tags TEXT, target_save_path TEXT, download_path TEXT,
+    content_layout TEXT NOT NULL, ratio_limit INTEGER NOT NULL, seeding_time_limit INTEGER NOT NULL,
+    inactive_seeding_time_limit INTEGER NOT NULL DEFAULT -2,
+    has_outer_pieces_priority INTEGER NOT NULL, has_seed_status INTEGER NOT NULL,
+    operating_mode TEXT NOT NULL, stopped INTEGER NOT NULL,
+    stop_condition TEXT NOT NULL DEFAULT `None`, libtorrent_resume_data BLOB NOT NULL,
+    metadata BLOB);";
+
+/// Hash versions a fixture torrent is created with
+pub enum Version {
+    /// SHA-1 piece hashes only
+    V1,
+    /// A v2 file tree only
+    V2,
+    /// Both SHA-1 piece hashes and a v2 file tree
+    Hybrid,
+}
+
+/// Returns a bencode byte string
+pub fn bytes(value: &str) -> Value {
+    Value::Bytes(value.as_bytes().to_vec())
+}
+
+/// Returns a bencode dictionary
+pub fn dict(entries: Vec<(&str, Value)>) -> Value {
+    Value::Dict(
+        entries
+            .into_iter()
+            .map(|(key, value)| (key.as_bytes().to_vec(), value))
+            .collect::<HashMap<_, _>>(),
+    )
+}
+
+/// Returns the info dictionary of a torrent with files of the given lengths
+///
+/// A single file is named after the torrent, multiple files are placed below a root folder.
+pub fn info(name: &str, piece_length: i64, files: &[(&str, i64)], version: Version) -> Value {
+    let total_size: i64 = files.iter().map(|(_, length)| length).sum();
+    let num_pieces = (total_size + piece_length - 1) / piece_length;
+    let mut entries = vec![
+        ("name", bytes(name)),
+        ("piece length", Value::Int(piece_length)),
+    ];
+    if matches!(version, Version::V1 | Version::Hybrid) {
+        entries.push(("pieces", Value::Bytes(vec![0xab; 20 * num_pieces as usize])));
+        match files {
+            [(_, length)] => entries.push(("length", Value::Int(*length))),
+            _ => entries.push((
+                "files",
+                Value::List(
+                    files
+                        .iter()
+                        .map(|(path, length)| {
+                            dict(vec![
+                                ("length", Value::Int(*length)),
+                                ("path", Value::List(path.split('/').map(bytes).collect())),
+                            ])
+                        })
+                        .collect(),
+                ),
+            )),
+        }
+    }
+    if matches!(version, Version::V2 | Version::Hybrid) {
+        entries.push(("meta version", Value::Int(2)));
+        let tree = files
+            .iter()
+            .map(|(path, length)| {
+                (
+                    *path,
+                    dict(vec![(
+                        "",
+                        dict(vec![
+                            ("length", Value::Int(*length)),
+                            ("pieces root", Value::Bytes(vec![0xcd; 32])),
+                        ]),
+                    )]),
+                )
+            })
+            .collect();
+        entries.push(("file tree", dict(tree)));
+    }
+    dict(entries)
+}
+
+/// Returns a bencoded .torrent file with an info dictionary, a tracker and extra top-level keys
+pub fn torrent_file(info: Value, extra: Vec<(&str, Value)>) -> Vec<u8> {
+    let mut entries = vec![
+        ("announce", bytes("http://tracker.example/announce")),
+        ("info", info),
+    ];
+    entries.extend(extra);
+    bencode(&dict(entries))
+}
+
+/// Bencodes a value
+pub fn bencode(value: &Value) -> Vec<u8> {
+    serde_bencode::to_bytes(value).unwrap()
+}
+
+/// Returns an empty directory in the system temporary directory, unique to the test and process
+pub fn temp_dir(name: &str) -> PathBuf {
+    let directory = std::env::temp_dir().join(format!("qbfrt-{}-{}", name, process::id()));
+    if directory.exists() {
+        fs::remove_dir_all(&directory).unwrap();
+    }
+    fs::create_dir_all(&directory).unwrap();
+    directory
+}
+
+/// Returns an in-memory database with an empty torrents table
+pub fn database() -> Connection {
+    let db = Connection::open_in_memory().unwrap();
+    db.execute(TORRENTS_TABLE, []).unwrap();
+    db
+}
@@ -0,0 +1,197 @@
+//! Tools for enforcing tracker URL policies
+//!
+//! Policies are read from a JSON file.
+//!
+//! ```json
+//! {
+//!     "https_hosts": ["some.tracker", "other.tracker"],
+//!     "drop_udp_on_private": true,
+//!     "remove_malformed": true
+//! }
+//! ```
+
+use crate::db::filter::{fetch_torrents, TorrentFilter};
+use crate::db::tracker_report::malformed_reason;
+use crate::db::tracker_url::{host_matches, tracker_host};
+use rusqlite::{named_params, Connection};
+use serde_derive::Deserialize;
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Tracker URL policy
+#[derive(Deserialize, Debug, Default)]
+#[serde(deny_unknown_fields)]
+pub struct TrackerPolicy {
+    /// Domains whose `http://` announce URLs are upgraded to `https://`, subdomains included
+    #[serde(default)]
+    pub https_hosts: Vec<String>,
+    /// Remove `udp://` trackers from private torrents
+    #[serde(default)]
+    pub drop_udp_on_private: bool,
+    /// Remove announce URLs that cannot be announced to
+    #[serde(default)]
+    pub remove_malformed: bool,
+}
+
+/// Policy run information
+#[derive(Debug)]
+pub struct PolicyRun {
+    /// Policy to enforce
+    pub policy: TrackerPolicy,
+    /// Only report what would change without modifying the database
+    pub dry_run: bool,
+}
+
+/// Reads a tracker policy from a JSON file
+pub fn load_policy(policy_file: &Path) -> Result<TrackerPolicy, Box<dyn Error>> {
+    let contents = fs::read_to_string(policy_file)?;
+    Ok(serde_json::from_str(&contents)?)
+}
+
+/// Enforces a tracker policy on the trackers of every torrent matching the filter
+///
+/// Malformed announce URLs are removed first, then `udp://` trackers are removed from private
+/// torrents, and finally `http://` trackers on the listed hosts are upgraded to `https://`. An upgrade
+/// whose `https://` URL the torrent already has removes the `http://` tracker, and tiers left empty
+/// are removed. Other trackers are kept exactly as they are, use `trackers dedup` to remove
+/// duplicates. Every change is reported, and with `dry_run` set the database is not modified.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::tracker_policy::{enforce_tracker_policy, load_policy, PolicyRun};
+/// let run = PolicyRun {
+///     policy: load_policy(Path::new("policy.json"))?,
+///     dry_run: true,
+/// };
+/// enforce_tracker_policy(&connection, run, &TorrentFilter::default(), false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the new tracker tiers of every changed torrent.
+pub fn enforce_tracker_policy(
+    db: &Connection,
+    run: PolicyRun,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    match run.dry_run {
+        true => println!("Policy: dry run, the database will not be modified"),
+        false => println!("Policy: enforcing tracker policy"),
+    }
+
+    let torrents = fetch_torrents(db, filter)?;
+
+    let mut num_updated = 0;
+    let mut num_removed = 0;
+    let mut num_dropped = 0;
+    let mut num_upgraded = 0;
+    for torrent in torrents {
+        let torrent_id = &torrent.data.torrent_id;
+        let is_private = torrent.is_private();
+        let mut libtorrent_resume_data = torrent.fastresume;
+
+        let mut changed = false;
+        let existing: Vec<&String> = libtorrent_resume_data.trackers.iter().flatten().collect();
+        let mut trackers: Vec<Vec<String>> = Vec::new();
+        for tier in &libtorrent_resume_data.trackers {
+            let mut new_tier = Vec::new();
+            for tracker in tier {
+                if run.policy.remove_malformed {
+                    if let Some(reason) = malformed_reason(tracker) {
+                        println!("{}: removed {}, {}", torrent_id, tracker, reason);
+                        num_removed += 1;
+                        changed = true;
+                        continue;
+                    }
+                }
+
+                let scheme = tracker
+                    .trim()
+                    .split_once("://")
+                    .map(|(scheme, _)| scheme.to_lowercase())
+                    .unwrap_or_default();
+                if run.policy.drop_udp_on_private && is_private && scheme == "udp" {
+                    println!("{}: dropped {} from private torrent", torrent_id, tracker);
+                    num_dropped += 1;
+                    changed = true;
+                    continue;
+                }
+
+                let upgrade = scheme == "http"
+                    && tracker_host(tracker).is_some_and(|host| {
+                        run.policy
+                            .https_hosts
+                            .iter()
+                            .any(|domain| host_matches(&host, domain))
+                    });
+                if !upgrade {
+                    new_tier.push(tracker.clone());
+                    continue;
+                }
+
+                // Only the scheme is replaced, the rest of the URL is kept as it is
+                let start = tracker.len() - tracker.trim_start().len();
+                let upgraded = format!(
+                    "{}https{}",
+                    &tracker[..start],
+                    &tracker[start + "http".len()..]
+                );
+                num_upgraded += 1;
+                changed = true;
+                if existing.contains(&&upgraded) {
+                    println!(
+                        "{}: removed {}, the torrent already has {}",
+                        torrent_id, tracker, upgraded
+                    );
+                    continue;
+                }
+                println!("{}: upgraded {} to {}", torrent_id, tracker, upgraded);
+                new_tier.push(upgraded);
+            }
+            // Tiers that were already empty are left alone
+            if !new_tier.is_empty() || tier.is_empty() {
+                trackers.push(new_tier);
+            }
+        }
+
+        if !changed {
+            continue;
+        }
+        libtorrent_resume_data.trackers = trackers;
+        if verbose {
+            for (index, tier) in libtorrent_resume_data.trackers.iter().enumerate() {
+                println!("{}: tier {} {:?}", torrent_id, index, tier);
+            }
+        }
+
+        num_updated += 1;
+        if run.dry_run {
+            continue;
+        }
+
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET libtorrent_resume_data = :lrd
+                WHERE id = :id",
+        )?;
+        update_stmt.execute(named_params! {
+            ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?,
+            ":id": torrent.data.id,
+        })?;
+    }
+
+    println!(
+        "Policy: {} malformed removed, {} udp dropped, {} upgraded to https",
+        num_removed, num_dropped, num_upgraded
+    );
+    match (num_updated, run.dry_run) {
+        (0, _) => println!("Policy: no torrents need changes"),
+        (1, true) => println!("Policy: 1 torrent would be updated"),
+        (_, true) => println!("Policy: {} torrents would be updated", num_updated),
+        (1, false) => println!("Policy: 1 torrent was updated"),
+        (_, false) => println!("Policy: {} torrents were updated", num_updated),
+    }
+
+    Ok(())
+}
@@ -0,0 +1,303 @@
+//! Tools for reporting on the trackers used across torrents
+
+use crate::db::filter::{fetch_torrents, TorrentFilter};
+use crate::db::tracker_url::tracker_host;
+use rusqlite::Connection;
+use std::collections::{BTreeMap, BTreeSet};
+use std::error::Error;
+use url::Url;
+
+/// Tracker URL schemes qB can announce to
+const SUPPORTED_SCHEMES: [&str; 3] = ["http", "https", "udp"];
+
+/// Hosts used by at least this many torrents are established trackers rather than typos
+const WELL_POPULATED: usize = 5;
+
+/// Totals for a single tracker host
+#[derive(Debug, Default)]
+struct HostSummary {
+    /// Number of torrents using the host
+    torrents: usize,
+    /// Total size of those torrents in bytes
+    size: u64,
+    /// Total amount uploaded by those torrents in bytes
+    uploaded: u64,
+    /// Total amount downloaded by those torrents in bytes
+    downloaded: u64,
+    /// Number of those torrents that are private
+    private: usize,
+    /// URL schemes used to announce to the host
+    schemes: BTreeSet<String>,
+    /// Distinct announce URLs for the host
+    urls: BTreeSet<String>,
+}
+
+/// Formats a number of bytes using binary units, e.g. `1.50 GiB`
+pub fn format_size(bytes: u64) -> String {
+    const UNITS: [&str; 6] = ["B", "KiB", "MiB", "GiB", "TiB", "PiB"];
+    let mut size = bytes as f64;
+    let mut unit = 0;
+    while size >= 1024.0 && unit < UNITS.len() - 1 {
+        size /= 1024.0;
+        unit += 1;
+    }
+    match unit {
+        0 => format!("{} {}", bytes, UNITS[0]),
+        _ => format!("{:.2} {}", size, UNITS[unit]),
+    }
+}
+
+/// Returns the number of single character edits needed to turn one string into another
+fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut previous: Vec<usize> = (0..=b.len()).collect();
+    for (i, a_char) in a.chars().enumerate() {
+        let mut current = vec![i + 1];
+        for (j, b_char) in b.iter().enumerate() {
+            let substitution = previous[j] + (a_char != *b_char) as usize;
+            current.push(substitution.min(previous[j + 1] + 1).min(current[j] + 1));
+        }
+        previous = current;
+    }
+    previous[b.len()]
+}
+
+/// Returns true if `host` looks like a typo of `other`
+///
+/// A typo is at most one edit per eight characters away, so short hosts such as `tl.org` and `tt.org`
+/// are never confused. Hosts that only differ in their digits, such as `tracker1.example.org` and
+/// `tracker2.example.org`, are usually separate trackers of the same site.
+fn is_near_miss(host: &str, other: &str) -> bool {
+    let without_digits = |host: &str| -> String {
+        host.chars()
+            .filter(|character| !character.is_ascii_digit())
+            .collect()
+    };
+    let distance = edit_distance(host, other);
+    distance > 0
+        && distance * 8 <= host.len().min(other.len())
+        && without_digits(host) != without_digits(other)
+}
+
+/// Returns the reason a tracker URL cannot be announced to, or `None` if it is well formed
+pub fn malformed_reason(announce_url: &str) -> Option<String> {
+    let Ok(url) = Url::parse(announce_url.trim()) else {
+        return Some(String::from("URL could not be parsed"));
+    };
+    if !SUPPORTED_SCHEMES.contains(&url.scheme()) {
+        return Some(format!("unsupported scheme {}", url.scheme()));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Some(String::from("URL has no host"));
+    }
+    if url.scheme() == "udp" && url.port().is_none() {
+        return Some(String::from("udp tracker has no port"));
+    }
+    None
+}
+
+/// Returns the reason a tracker URL looks broken, or `None` if it looks usable
+fn url_problem(announce_url: &str) -> Option<String> {
+    if let Some(reason) = malformed_reason(announce_url) {
+        return Some(reason);
+    }
+    let host = tracker_host(announce_url).unwrap_or_default();
+    if !host.contains(['.', ':']) && host != "localhost" {
+        return Some(format!("host {host} has no domain"));
+    }
+    None
+}
+
+/// Reports every tracker host used by the torrents matching the filter, along with suspicious trackers
+///
+/// For each host the number of torrents, their total size, the totals uploaded and downloaded from the
+/// fastresume data, the number of private torrents and the URL schemes in use are shown. Trackers
+/// are listed as suspicious if their URL cannot be used, or if their host is a near miss of a more
+/// common host, which usually means a typo. Hosts used by many torrents are not reported as near
+/// misses, since a typo rarely spreads that far. Nothing is modified.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::tracker_report::report_trackers;
+/// report_trackers(&connection, &TorrentFilter::default(), false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will also output every distinct announce URL for each host.
+pub fn report_trackers(
+    db: &Connection,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    println!("Trackers: reporting trackers");
+
+    let torrents = fetch_torrents(db, filter)?;
+
+    let mut hosts: BTreeMap<String, HostSummary> = BTreeMap::new();
+    let mut problems: BTreeMap<(String, String), usize> = BTreeMap::new();
+    let mut num_without_trackers = 0;
+    for torrent in &torrents {
+        let mut torrent_hosts = BTreeSet::new();
+        let mut torrent_problems = BTreeSet::new();
+        for tracker in torrent.fastresume.trackers.iter().flatten() {
+            if let Some(problem) = url_problem(tracker) {
+                torrent_problems.insert((tracker.clone(), problem));
+            }
+            let Some(host) = tracker_host(tracker) else {
+                continue;
+            };
+            let summary = hosts.entry(host.clone()).or_default();
+            if let Ok(url) = Url::parse(tracker.trim()) {
+                summary.schemes.insert(url.scheme().to_string());
+            }
+            summary.urls.insert(tracker.clone());
+            torrent_hosts.insert(host);
+        }
+
+        if torrent
+            .fastresume
+            .trackers
+            .iter()
+            .flatten()
+            .next()
+            .is_none()
+        {
+            num_without_trackers += 1;
+        }
+
+        let size = torrent.total_size().max(0) as u64;
+        let private = torrent.is_private();
+        for host in torrent_hosts {
+            let summary = hosts.entry(host).or_default();
+            summary.torrents += 1;
+            summary.size += size;
+            summary.uploaded += torrent.fastresume.total_uploaded;
+            summary.downloaded += torrent.fastresume.total_downloaded;
+            summary.private += private as usize;
+        }
+        for problem in torrent_problems {
+            *problems.entry(problem).or_default() += 1;
+        }
+    }
+
+    // A rarely used host a few edits away from a host used by more torrents is most likely a typo
+    let mut near_misses = Vec::new();
+    for (host, summary) in &hosts {
+        if summary.torrents >= WELL_POPULATED {
+            continue;
+        }
+        let likely_host = hosts
+            .iter()
+            .filter(|(other, other_summary)| {
+                other_summary.torrents > summary.torrents && is_near_miss(host, other)
+            })
+            .max_by_key(|(_, other_summary)| other_summary.torrents);
+        if let Some((other, _)) = likely_host {
+            near_misses.push((host, other, summary.torrents));
+        }
+    }
+
+    let mut sorted_hosts: Vec<(&String, &HostSummary)> = hosts.iter().collect();
+    sorted_hosts.sort_by(|(a, a_summary), (b, b_summary)| {
+        b_summary.torrents.cmp(&a_summary.torrents).then(a.cmp(b))
+    });
+    for (host, summary) in sorted_hosts {
+        println!(
+            "{}: {} {}, {}, uploaded {}, downloaded {}, {} private, schemes {}",
+            host,
+            summary.torrents,
+            if summary.torrents == 1 {
+                "torrent"
+            } else {
+                "torrents"
+            },
+            format_size(summary.size),
+            format_size(summary.uploaded),
+            format_size(summary.downloaded),
+            summary.private,
+            summary
+                .schemes
+                .iter()
+                .map(String::as_str)
+                .collect::<Vec<&str>>()
+                .join(", ")
+        );
+        if verbose {
+            for url in &summary.urls {
+                println!("{}: {}", host, url);
+            }
+        }
+    }
+
+    for ((url, problem), count) in &problems {
+        match count {
+            1 => println!(
+                "Trackers: suspicious tracker {} on 1 torrent, {}",
+                url, problem
+            ),
+            _ => println!(
+                "Trackers: suspicious tracker {} on {} torrents, {}",
+                url, count, problem
+            ),
+        }
+    }
+    for (host, likely_host, count) in &near_misses {
+        match count {
+            1 => println!(
+                "Trackers: suspicious host {} on 1 torrent, possibly a typo of {}",
+                host, likely_host
+            ),
+            _ => println!(
+                "Trackers: suspicious host {} on {} torrents, possibly a typo of {}",
+                host, count, likely_host
+            ),
+        }
+    }
+
+    match num_without_trackers {
+        0 => {}
+        1 => println!("Trackers: 1 torrent has no trackers"),
+        _ => println!(
+            "Trackers: {} torrents have no trackers",
+            num_without_trackers
+        ),
+    }
+    match (hosts.len(), torrents.len()) {
+        (1, 1) => println!("Trackers: 1 host across 1 torrent"),
+        (1, _) => println!("Trackers: 1 host across {} torrents", torrents.len()),
+        (_, 1) => println!("Trackers: {} hosts across 1 torrent", hosts.len()),
+        _ => println!(
+            "Trackers: {} hosts across {} torrents",
+            hosts.len(),
+            torrents.len()
+        ),
+    }
+    match problems.len() + near_misses.len() {
+        0 => println!("Trackers: no suspicious trackers were found"),
+        1 => println!("Trackers: 1 suspicious tracker was found"),
+        count => println!("Trackers: {} suspicious trackers were found", count),
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn detects_near_miss_hosts() {
+        let cases = [
+            ("tracker.exmaple.org", "tracker.example.org", true),
+            ("opentracker.org", "opentrackr.org", true),
+            ("tracker.example.org", "tracker.example.org", false),
+            ("tracker1.example.org", "tracker2.example.org", false),
+            ("tracker.example.org", "tracker12.example.org", false),
+            ("tl.org", "tt.org", false),
+            ("tracker.example.org", "tracker.sample.net", false),
+        ];
+        for (host, other, near_miss) in cases {
+            assert_eq!(is_near_miss(host, other), near_miss, "{host} {other}");
+        }
+    }
+}
@@ -4,6 +4,7 @@ use crate::db::db_structs::{FastresumeData, FetchedLibtorrentResumeData};
 use rusqlite::{named_params, Connection};
 use serde_rusqlite::from_rows;
 use std::error::Error;
+use url::Url;
 
 /// Tracker url information
 #[derive(Debug)]
@@ -14,6 +15,20 @@ pub struct TrackerUrl {
     pub new: String,
 }
 
+/// Returns the lowercase host of a tracker announce URL, or `None` if it cannot be parsed
+pub fn tracker_host(url: &str) -> Option<String> {
+    Url::parse(url.trim())
+        .ok()
+        .and_then(|url| url.host_str().map(str::to_lowercase))
+        .filter(|host| !host.is_empty())
+}
+
+/// Returns true if the host is `domain` or one of its subdomains
+pub fn host_matches(host: &str, domain: &str) -> bool {
+    let domain = domain.to_lowercase();
+    host == domain || host.ends_with(&format!(".{domain}"))
+}
+
 /// Performs a string replace operation on torrent trackers
 ///
 /// ## Example
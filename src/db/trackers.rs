@@ -0,0 +1,173 @@
+//! Tools for editing torrent tracker lists and tiers
+
+use crate::db::db_structs::replace_announce;
+use crate::db::filter::{fetch_torrents, TorrentFilter};
+use rusqlite::{named_params, Connection};
+use std::error::Error;
+
+/// Tracker list operations
+#[derive(Debug)]
+pub enum TrackerOperation {
+    /// Add a tracker to a tier, or to a new last tier if no tier is given
+    Add {
+        /// Tracker announce URL
+        url: String,
+        /// Zero-based tier index
+        tier: Option<usize>,
+    },
+    /// Remove every tracker whose URL contains the pattern
+    Remove(String),
+    /// Remove repeated trackers, keeping the first one
+    Dedup,
+    /// Move every tracker into a single tier
+    Collapse,
+    /// Move every tracker into its own tier
+    Split,
+}
+
+/// Tracker edit information
+#[derive(Debug)]
+pub struct TrackerEdit {
+    /// Operation to perform on the tracker list
+    pub operation: TrackerOperation,
+    /// Also write the tracker list to the announce-list of the metadata
+    pub update_metadata: bool,
+}
+
+/// Applies a tracker operation to a list of tiers, dropping tiers left empty
+pub fn edit_tiers(tiers: &[Vec<String>], operation: &TrackerOperation) -> Vec<Vec<String>> {
+    let mut tiers = tiers.to_vec();
+    match operation {
+        TrackerOperation::Add { url, tier } => {
+            if !tiers.iter().flatten().any(|tracker| tracker == url) {
+                match tiers.get_mut(tier.unwrap_or(usize::MAX)) {
+                    Some(tier) => tier.push(url.clone()),
+                    None => tiers.push(vec![url.clone()]),
+                }
+            }
+        }
+        TrackerOperation::Remove(pattern) => {
+            for tier in tiers.iter_mut() {
+                tier.retain(|tracker| !tracker.contains(pattern.as_str()));
+            }
+        }
+        TrackerOperation::Dedup => {
+            let mut seen: Vec<String> = Vec::new();
+            for tier in tiers.iter_mut() {
+                tier.retain(|tracker| {
+                    let tracker = tracker.trim().to_string();
+                    match seen.contains(&tracker) {
+                        true => false,
+                        false => {
+                            seen.push(tracker);
+                            true
+                        }
+                    }
+                });
+            }
+        }
+        TrackerOperation::Collapse => tiers = vec![tiers.concat()],
+        TrackerOperation::Split => {
+            tiers = tiers
+                .concat()
+                .into_iter()
+                .map(|tracker| vec![tracker])
+                .collect()
+        }
+    }
+    tiers.retain(|tier| !tier.is_empty());
+    tiers
+}
+
+/// Edits the tracker tiers of every torrent matching the filter
+///
+/// Trackers are stored as a list of tiers in `libtorrent_resume_data`, tried in order with the
+/// trackers of each tier tried together. qB does not normally keep trackers in the `metadata` column,
+/// but with `update_metadata` set the new list is written to its `announce` and `announce-list`
+/// fields, which are used when the torrent is exported.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::trackers::{edit_trackers, TrackerEdit, TrackerOperation};
+/// let edit = TrackerEdit {
+///     operation: TrackerOperation::Add {
+///         url: String::from("udp://tracker.example:1337/announce"),
+///         tier: Some(0),
+///     },
+///     update_metadata: false,
+/// };
+/// edit_trackers(&connection, edit, &TorrentFilter::default(), false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the torrent hash and new tracker tiers for every
+/// updated torrent.
+pub fn edit_trackers(
+    db: &Connection,
+    edit: TrackerEdit,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    println!("Trackers: editing tracker lists");
+
+    let torrents = fetch_torrents(db, filter)?;
+
+    let mut num_updated = 0;
+    for torrent in torrents {
+        let mut libtorrent_resume_data = torrent.fastresume;
+        let trackers = edit_tiers(&libtorrent_resume_data.trackers, &edit.operation);
+        let trackers_changed = trackers != libtorrent_resume_data.trackers;
+
+        let mut metadata_changed = false;
+        let mut metadata = torrent.data.metadata;
+        if edit.update_metadata {
+            if let Some(bencoded_metadata) = &metadata {
+                if let Some(updated) = replace_announce(bencoded_metadata, &trackers)? {
+                    metadata = Some(updated);
+                    metadata_changed = true;
+                }
+            }
+        }
+
+        if !trackers_changed && !metadata_changed {
+            continue;
+        }
+        libtorrent_resume_data.trackers = trackers;
+
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET libtorrent_resume_data = :lrd, metadata = :metadata
+                WHERE id = :id
+                RETURNING torrent_id;",
+        )?;
+        update_stmt.query_row(
+            named_params! {
+                ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?,
+                ":metadata": metadata,
+                ":id": torrent.data.id,
+            },
+            |row| {
+                let updated_row_id = row.get::<usize, String>(0)?;
+
+                if verbose {
+                    println!("Trackers: updated trackers for {}", updated_row_id);
+                    for (index, tier) in libtorrent_resume_data.trackers.iter().enumerate() {
+                        println!("{}: tier {} {:?}", updated_row_id, index, tier);
+                    }
+                }
+
+                num_updated += 1;
+
+                Ok(())
+            },
+        )?;
+    }
+
+    match num_updated {
+        0 => println!("Trackers: no torrents were updated"),
+        1 => println!("Trackers: 1 torrent was updated"),
+        _ => println!("Trackers: {} torrents were updated", num_updated),
+    }
+
+    Ok(())
+}
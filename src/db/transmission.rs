@@ -0,0 +1,345 @@
+//! Tools for reading and writing torrents in a Transmission config directory
+
+use crate::db::db_structs::TorrentMetadata;
+use crate::db::export::{pack_bitfield, ExportedTorrent};
+use crate::db::import::{read_torrent_file, unpack_bitfield, ImportedTorrent};
+use serde_bytes::ByteBuf;
+use serde_derive::{Deserialize, Serialize};
+use std::error::Error;
+use std::fs;
+use std::path::Path;
+
+/// Size of the blocks Transmission tracks completion in
+const BLOCK_SIZE: i64 = 16384;
+
+/// Transmission resume file, `resume/<name>.resume`
+///
+/// Only the fields needed for importing and exporting are kept, every field is optional since they
+/// vary between Transmission versions.
+#[derive(Serialize, Deserialize, Debug)]
+struct TransmissionResume {
+    /// Directory the content is saved in
+    destination: Option<String>,
+    /// Torrent name, only differs from the metadata if the torrent was renamed
+    name: Option<String>,
+    /// Path of every file, only present if a file was renamed
+    files: Option<Vec<String>>,
+    /// Torrent labels
+    labels: Option<Vec<String>>,
+    /// Total uploaded in bytes
+    uploaded: Option<u64>,
+    /// Total downloaded in bytes
+    downloaded: Option<u64>,
+    /// Date the torrent was added
+    #[serde(rename = "added-date")]
+    added_date: Option<i64>,
+    /// Date the torrent was completed
+    #[serde(rename = "done-date")]
+    done_date: Option<i64>,
+    /// Time spent seeding in seconds
+    #[serde(rename = "seeding-time-seconds")]
+    seeding_time: Option<i64>,
+    /// Time spent downloading in seconds
+    #[serde(rename = "downloading-time-seconds")]
+    downloading_time: Option<i64>,
+    /// Torrent is stopped
+    paused: Option<i64>,
+    /// Piece and block completion
+    progress: Option<TransmissionProgress>,
+}
+
+/// Completion within a Transmission resume file
+///
+/// Each field is either `all`, `none` or a raw bitfield.
+#[derive(Serialize, Deserialize, Debug)]
+struct TransmissionProgress {
+    /// Downloaded blocks, used by Transmission 2.x and later
+    blocks: Option<ByteBuf>,
+    /// Verified pieces, used by Transmission 4.x
+    pieces: Option<ByteBuf>,
+    /// `all` if every piece has been downloaded, used by older versions
+    have: Option<ByteBuf>,
+    /// Downloaded pieces, used by older versions
+    bitfield: Option<ByteBuf>,
+}
+
+/// Encodes completion as a Transmission completion field
+fn encode_completion(completion: &[bool]) -> ByteBuf {
+    match (
+        completion.iter().all(|have| *have),
+        completion.iter().any(|have| *have),
+    ) {
+        (true, _) => ByteBuf::from(b"all".to_vec()),
+        (false, false) => ByteBuf::from(b"none".to_vec()),
+        (false, true) => ByteBuf::from(pack_bitfield(completion)),
+    }
+}
+
+/// Decodes a Transmission completion field of `count` bits
+fn decode_completion(field: &[u8], count: usize) -> Vec<bool> {
+    match field {
+        b"all" => vec![true; count],
+        b"none" => vec![false; count],
+        bitfield => unpack_bitfield(bitfield, count),
+    }
+}
+
+/// Converts block completion to piece completion, a piece is complete if all of its blocks are
+fn blocks_to_pieces(blocks: &[u8], metadata: &TorrentMetadata) -> Vec<bool> {
+    let total_size = metadata.total_size();
+    let piece_length = metadata.piece_length();
+    if total_size <= 0 || piece_length <= 0 {
+        return Vec::new();
+    }
+    let num_blocks = (total_size + BLOCK_SIZE - 1) / BLOCK_SIZE;
+    let blocks = decode_completion(blocks, num_blocks as usize);
+    (0..metadata.num_pieces() as i64)
+        .map(|piece| {
+            let start = piece * piece_length;
+            let end = (start + piece_length).min(total_size);
+            start < end
+                && (start / BLOCK_SIZE..=(end - 1) / BLOCK_SIZE)
+                    .all(|block| blocks.get(block as usize).is_some_and(|have| *have))
+        })
+        .collect()
+}
+
+/// Converts piece completion to block completion, a block is complete if its piece is
+fn pieces_to_blocks(pieces: &[bool], metadata: &TorrentMetadata) -> Vec<bool> {
+    let total_size = metadata.total_size();
+    let piece_length = metadata.piece_length();
+    if total_size <= 0 || piece_length <= 0 {
+        return Vec::new();
+    }
+    let num_blocks = (total_size + BLOCK_SIZE - 1) / BLOCK_SIZE;
+    (0..num_blocks)
+        .map(|block| {
+            let piece = block * BLOCK_SIZE / piece_length;
+            pieces.get(piece as usize).is_some_and(|have| *have)
+        })
+        .collect()
+}
+
+/// Reads a single torrent from its .torrent file and resume file
+fn read_torrent(
+    torrent_file: &Path,
+    resume_file: &Path,
+) -> Result<ImportedTorrent, Box<dyn Error>> {
+    let (contents, metadata) = read_torrent_file(torrent_file)?;
+    let resume: TransmissionResume = serde_bencode::from_bytes(&fs::read(resume_file)?)?;
+    let Some(destination) = resume.destination else {
+        return Err("resume file has no destination".into());
+    };
+
+    let original_paths = metadata.file_paths();
+    let original_name = metadata.name();
+    let pieces = match resume.progress {
+        Some(TransmissionProgress {
+            blocks: Some(blocks),
+            ..
+        }) => blocks_to_pieces(&blocks, &metadata),
+        Some(TransmissionProgress {
+            have: Some(have), ..
+        }) if have.as_slice() == b"all" => vec![true; metadata.num_pieces()],
+        Some(TransmissionProgress {
+            bitfield: Some(bitfield),
+            ..
+        }) => decode_completion(&bitfield, metadata.num_pieces()),
+        _ => Vec::new(),
+    };
+
+    let mut torrent = ImportedTorrent::new(contents, metadata, destination);
+    torrent.name = resume.name.filter(|name| *name != original_name);
+    torrent.mapped_files = match (resume.files, &torrent.name) {
+        (Some(files), _) if files.len() == original_paths.len() => Some(files),
+        // A renamed torrent has its root folder or single file renamed
+        (_, Some(name)) => Some(
+            original_paths
+                .iter()
+                .map(|path| match path.split_once('/') {
+                    Some((_, rest)) => format!("{name}/{rest}"),
+                    None => name.clone(),
+                })
+                .collect(),
+        ),
+        _ => None,
+    }
+    .filter(|paths| *paths != original_paths);
+    torrent.tags = resume.labels.unwrap_or_default();
+    torrent.pieces = pieces;
+    torrent.uploaded = resume.uploaded.unwrap_or_default();
+    torrent.downloaded = resume.downloaded.unwrap_or_default();
+    torrent.added_time = resume.added_date.unwrap_or_default();
+    torrent.completed_time = resume.done_date.unwrap_or_default();
+    torrent.seeding_time = resume.seeding_time.unwrap_or_default();
+    torrent.active_time = torrent.seeding_time + resume.downloading_time.unwrap_or_default();
+    torrent.paused = resume.paused.unwrap_or_default() != 0;
+
+    Ok(torrent)
+}
+
+/// Reads every torrent from a Transmission config directory
+///
+/// Each `torrents/<name>.torrent` file is paired with the `resume/<name>.resume` file of the same
+/// name. Torrents that cannot be read are reported and skipped. Transmission labels become tags.
+pub fn read_transmission(directory: &Path) -> Result<Vec<ImportedTorrent>, Box<dyn Error>> {
+    let torrents_directory = directory.join("torrents");
+    let resume_directory = directory.join("resume");
+    if !torrents_directory.is_dir() || !resume_directory.is_dir() {
+        return Err(format!(
+            "{:?} does not contain torrents and resume directories",
+            directory.display()
+        )
+        .into());
+    }
+
+    let mut torrent_files: Vec<_> = fs::read_dir(&torrents_directory)?
+        .map(|entry| entry.map(|entry| entry.path()))
+        .collect::<Result<_, _>>()?;
+    torrent_files.retain(|path| {
+        path.extension()
+            .is_some_and(|extension| extension == "torrent")
+    });
+    torrent_files.sort();
+
+    let mut torrents = Vec::new();
+    for torrent_file in torrent_files {
+        let resume_file = resume_directory
+            .join(torrent_file.file_name().unwrap_or_default())
+            .with_extension("resume");
+        if !resume_file.is_file() {
+            println!(
+                "Import: skipping {:?}, no resume file",
+                torrent_file.display()
+            );
+            continue;
+        }
+        match read_torrent(&torrent_file, &resume_file) {
+            Ok(torrent) => torrents.push(torrent),
+            Err(err) => println!("Import: skipping {:?}, {}", torrent_file.display(), err),
+        }
+    }
+
+    Ok(torrents)
+}
+
+/// Writes torrents to a Transmission config directory
+///
+/// Each torrent is written as `torrents/<hash>.torrent` and `resume/<hash>.resume`, the naming used
+/// by Transmission 4. The qB category and tags become labels. Renamed files keep their names.
+pub fn write_transmission(
+    directory: &Path,
+    torrents: &[ExportedTorrent],
+) -> Result<(), Box<dyn Error>> {
+    let torrents_directory = directory.join("torrents");
+    let resume_directory = directory.join("resume");
+    fs::create_dir_all(&torrents_directory)?;
+    fs::create_dir_all(&resume_directory)?;
+
+    for torrent in torrents {
+        let fastresume = &torrent.fastresume;
+        let original_paths = torrent.metadata.file_paths();
+        let root = torrent
+            .file_paths
+            .first()
+            .map(|path| match path.split_once('/') {
+                Some((root, _)) => root.to_string(),
+                None => path.clone(),
+            });
+
+        let mut labels: Vec<String> = torrent.category.iter().cloned().collect();
+        for tag in &torrent.tags {
+            if !labels.contains(tag) {
+                labels.push(tag.clone());
+            }
+        }
+        let pieces = encode_completion(&torrent.pieces);
+        let resume = TransmissionResume {
+            destination: Some(fastresume.save_path.clone()),
+            name: root.filter(|root| *root != torrent.metadata.name()),
+            files: (torrent.file_paths != original_paths).then(|| torrent.file_paths.clone()),
+            labels: (!labels.is_empty()).then_some(labels),
+            uploaded: Some(fastresume.total_uploaded),
+            downloaded: Some(fastresume.total_downloaded),
+            added_date: Some(fastresume.added_time),
+            done_date: Some(fastresume.completed_time),
+            seeding_time: Some(fastresume.seeding_time as i64),
+            downloading_time: Some(
+                (fastresume.active_time - fastresume.seeding_time as i64).max(0),
+            ),
+            paused: Some(fastresume.paused as i64),
+            progress: (!torrent.pieces.is_empty()).then(|| TransmissionProgress {
+                blocks: Some(encode_completion(&pieces_to_blocks(
+                    &torrent.pieces,
+                    &torrent.metadata,
+                ))),
+                pieces: Some(pieces),
+                have: None,
+                bitfield: None,
+            }),
+        };
+
+        fs::write(
+            torrents_directory.join(format!("{}.torrent", torrent.hash)),
+            &torrent.torrent_file,
+        )?;
+        fs::write(
+            resume_directory.join(format!("{}.resume", torrent.hash)),
+            serde_bencode::to_bytes(&resume)?,
+        )?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::db::test_fixtures::{info, torrent_file, Version};
+
+    /// Returns the metadata of a v1 torrent with the given piece length and file lengths
+    fn v1_metadata(piece_length: i64, files: &[(&str, i64)]) -> TorrentMetadata {
+        let info = info("Pack", piece_length, files, Version::V1);
+        serde_bencode::from_bytes(&torrent_file(info, Vec::new())).unwrap()
+    }
+
+    #[test]
+    fn decodes_completion() {
+        assert_eq!(decode_completion(b"all", 3), vec![true; 3]);
+        assert_eq!(decode_completion(b"none", 2), vec![false; 2]);
+        assert_eq!(
+            decode_completion(&[0b1010_0000], 4),
+            vec![true, false, true, false]
+        );
+        assert_eq!(
+            decode_completion(&encode_completion(&[false, true, true]), 3),
+            vec![false, true, true]
+        );
+    }
+
+    #[test]
+    fn converts_blocks_to_pieces() {
+        // Two blocks per piece, the last piece holds a single short block
+        let metadata = v1_metadata(2 * BLOCK_SIZE, &[("a.iso", 4 * BLOCK_SIZE), ("b.txt", 100)]);
+        assert_eq!(
+            blocks_to_pieces(&[0b1110_1000], &metadata),
+            vec![true, false, true]
+        );
+        assert_eq!(blocks_to_pieces(b"all", &metadata), vec![true; 3]);
+        assert_eq!(blocks_to_pieces(b"none", &metadata), vec![false; 3]);
+        // Spare bits are ignored and missing bytes count as missing blocks
+        assert_eq!(blocks_to_pieces(&[0xff], &metadata), vec![true; 3]);
+        assert_eq!(blocks_to_pieces(&[], &metadata), vec![false; 3]);
+
+        // Four pieces per block
+        let metadata = v1_metadata(BLOCK_SIZE / 4, &[("a.iso", 2 * BLOCK_SIZE)]);
+        assert_eq!(
+            blocks_to_pieces(&[0b1000_0000], &metadata),
+            [vec![true; 4], vec![false; 4]].concat()
+        );
+        assert_eq!(
+            pieces_to_blocks(&blocks_to_pieces(&[0b0100_0000], &metadata), &metadata),
+            vec![false, true]
+        );
+    }
+}
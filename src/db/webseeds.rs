@@ -0,0 +1,142 @@
+//! Tools for editing torrent web seeds and HTTP seeds
+
+use crate::db::filter::{fetch_torrents, TorrentFilter};
+use rusqlite::{named_params, Connection};
+use std::error::Error;
+
+/// Web seed operations
+#[derive(Debug)]
+pub enum WebSeedOperation {
+    /// Add a seed URL
+    Add(String),
+    /// Remove every seed URL containing the pattern
+    Remove(String),
+    /// Replace a string in every seed URL
+    Replace {
+        /// Existing string
+        old: String,
+        /// New string
+        new: String,
+    },
+}
+
+/// Web seed edit information
+#[derive(Debug)]
+pub struct WebSeedEdit {
+    /// Operation to perform on the seed URLs
+    pub operation: WebSeedOperation,
+    /// Edit the HTTP seeds (BEP 17) instead of the web seeds (BEP 19)
+    pub http_seeds: bool,
+}
+
+/// Applies a web seed operation to a list of seed URLs, dropping duplicates the operation creates
+pub fn edit_seeds(seeds: &[String], operation: &WebSeedOperation) -> Vec<String> {
+    let mut seeds = match operation {
+        WebSeedOperation::Add(url) => [seeds, std::slice::from_ref(url)].concat(),
+        WebSeedOperation::Remove(pattern) => seeds
+            .iter()
+            .filter(|seed| !seed.contains(pattern.as_str()))
+            .cloned()
+            .collect(),
+        WebSeedOperation::Replace { old, new } => {
+            seeds.iter().map(|seed| seed.replace(old, new)).collect()
+        }
+    };
+    let mut seen: Vec<String> = Vec::new();
+    seeds.retain(|seed| match seen.contains(seed) {
+        true => false,
+        false => {
+            seen.push(seed.clone());
+            true
+        }
+    });
+    seeds
+}
+
+/// Adds, removes or rewrites the web seeds of every torrent matching the filter
+///
+/// Web seeds (BEP 19) are stored in the `url-list` of `libtorrent_resume_data` and HTTP seeds
+/// (BEP 17) in its `httpseeds` list. Only one of the two lists is edited at a time.
+///
+/// ## Example
+/// ```rs
+/// use qbfrt::db::webseeds::{edit_webseeds, WebSeedEdit, WebSeedOperation};
+/// let edit = WebSeedEdit {
+///     operation: WebSeedOperation::Replace {
+///         old: String::from("https://old.mirror/"),
+///         new: String::from("https://new.mirror/"),
+///     },
+///     http_seeds: false,
+/// };
+/// edit_webseeds(&connection, edit, &TorrentFilter::default(), false);
+/// ```
+///
+/// ## Verbose output
+/// If verbose output is enabled it will output the torrent hash and new seed URLs for every updated
+/// torrent.
+pub fn edit_webseeds(
+    db: &Connection,
+    edit: WebSeedEdit,
+    filter: &TorrentFilter,
+    verbose: bool,
+) -> Result<(), Box<dyn Error>> {
+    let seed_kind = match edit.http_seeds {
+        true => "HTTP seeds",
+        false => "web seeds",
+    };
+    println!("Web seeds: editing {}", seed_kind);
+
+    let torrents = fetch_torrents(db, filter)?;
+
+    let mut num_updated = 0;
+    for torrent in torrents {
+        let mut libtorrent_resume_data = torrent.fastresume;
+        let seeds = match edit.http_seeds {
+            true => &mut libtorrent_resume_data.httpseeds,
+            false => &mut libtorrent_resume_data.url_list,
+        };
+
+        let new_seeds = edit_seeds(seeds, &edit.operation);
+        if new_seeds == *seeds {
+            continue;
+        }
+        *seeds = new_seeds;
+
+        let mut update_stmt = db.prepare(
+            "UPDATE torrents
+                SET libtorrent_resume_data = :lrd
+                WHERE id = :id
+                RETURNING torrent_id;",
+        )?;
+        update_stmt.query_row(
+            named_params! {
+                ":lrd": serde_bencode::to_bytes(&libtorrent_resume_data)?,
+                ":id": torrent.data.id,
+            },
+            |row| {
+                let updated_row_id = row.get::<usize, String>(0)?;
+
+                if verbose {
+                    let seeds = match edit.http_seeds {
+                        true => &libtorrent_resume_data.httpseeds,
+                        false => &libtorrent_resume_data.url_list,
+                    };
+                    println!("Web seeds: updated {} for {}", seed_kind, updated_row_id);
+                    println!("{}: new {} are {:?}", updated_row_id, seed_kind, seeds);
+                }
+
+                num_updated += 1;
+
+                Ok(())
+            },
+        )?;
+    }
+
+    match num_updated {
+        0 => println!("Web seeds: no torrents were updated"),
+        1 => println!("Web seeds: 1 torrent was updated"),
+        _ => println!("Web seeds: {} torrents were updated", num_updated),
+    }
+
+    Ok(())
+}
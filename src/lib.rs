@@ -8,6 +8,27 @@
 //!     - Change files to a new drive or directory without having to move torrents in qBittorrent or recheck all of the torrent data
 //!     - Migrate from qBittorrent on Windows to Linux without having to recheck the torrent data
 //! - Mass update the tracker URLs for torrents in the SQLite database
+//! - Check the SQLite database for problems
+//! - Repair torrents where the two stored save paths disagree
+//! - Migrate every save path and download path to new drives or mount points
+//! - Set, clear or rename categories in bulk
+//! - Add, remove, rename or replace tags in bulk
+//! - Automatically tag and categorise torrents using rules
+//! - Set seeding, rate and connection limits in bulk
+//! - Sort, reorder and compact the download queue
+//! - Start, stop and switch the management mode of torrents in bulk
+//! - Rename torrents, their root folder and individual files
+//! - Add, remove, deduplicate and reorder tracker tiers
+//! - Rotate private tracker passkeys
+//! - Add, remove or rewrite web seeds in bulk
+//! - Enforce HTTPS and other tracker policies
+//! - Summarise transfer statistics
+//! - Reset or adjust transfer statistics
+//! - Import torrents from Transmission, Deluge and rTorrent
+//! - Export torrents to Transmission, Deluge and rTorrent
+//! - Merge torrents from another torrents.db
+//! - Compare two torrents.db files
+//! - Report tracker usage and suspicious trackers
 //!
 //! **More functionality to come!**
 
@@ -15,3 +36,4 @@
 
 pub mod config;
 pub mod db;
+pub mod path;
@@ -1,27 +1,218 @@
 use figlet_rs::FIGfont;
-use qbfrt::config::Config;
-use qbfrt::db::{save_path, tracker_url, DB};
+use qbfrt::config::{Command, Config};
+use qbfrt::db::{
+    category, counters, diff, doctor, export, import, limits, merge, migrate, passkey, queue,
+    rename, rules, save_path, state, stats, tag, tracker_policy, tracker_report, tracker_url,
+    trackers, webseeds, DB,
+};
 use std::error::Error;
 use std::process;
 
 fn main() -> Result<(), Box<dyn Error>> {
-    println!("{}", FIGfont::standard().unwrap().convert("qbfrt").unwrap());
-
-    let config = Config::build().unwrap_or_else(|err| {
+    let mut config = Config::build().unwrap_or_else(|err| {
+        println!("{}", FIGfont::standard().unwrap().convert("qbfrt").unwrap());
         println!("Problem parsing arguments: {err}");
         process::exit(1);
     });
 
-    DB::backup(&config).unwrap_or_else(|err| {
-        println!("Could not backup database: {err}");
-        process::exit(1);
-    });
+    if !config.command.as_ref().is_some_and(Command::is_quiet) {
+        println!("{}", FIGfont::standard().unwrap().convert("qbfrt").unwrap());
+    }
 
-    let db = DB::connect(&config).unwrap_or_else(|err| {
+    // Comparing two given databases leaves the configured database alone
+    if let Some(Command::Diff(run)) = config
+        .command
+        .take_if(|command| matches!(command, Command::Diff(run) if run.new.is_some()))
+    {
+        diff::diff_databases(None, run, &config.filter, config.verbose).unwrap_or_else(|err| {
+            println!("Could not compare databases: {err}");
+            process::exit(1);
+        });
+        return Ok(());
+    }
+
+    let read_only = config.command.as_ref().is_some_and(Command::is_read_only);
+
+    if !read_only {
+        DB::backup(&config).unwrap_or_else(|err| {
+            println!("Could not backup database: {err}");
+            process::exit(1);
+        });
+    }
+
+    let db = if read_only {
+        DB::connect_read_only(&config)
+    } else {
+        DB::connect(&config)
+    }
+    .unwrap_or_else(|err| {
         println!("Could not connect to database: {err}");
         process::exit(1);
     });
 
+    match config.command {
+        Some(Command::Doctor) => {
+            let problems = doctor::check_database(&db, config.verbose).unwrap_or_else(|err| {
+                println!("Could not check database: {err}");
+                process::exit(1);
+            });
+
+            if problems > 0 {
+                process::exit(1);
+            }
+        }
+        Some(Command::Repair(repair)) => {
+            save_path::repair_save_path(&db, repair, config.verbose).unwrap_or_else(|err| {
+                println!("Could not repair save paths: {err}");
+                process::exit(1);
+            });
+        }
+        Some(Command::Migrate(migration)) => {
+            migrate::migrate_paths(&db, migration, config.verbose).unwrap_or_else(|err| {
+                println!("Could not migrate paths: {err}");
+                process::exit(1);
+            });
+        }
+        Some(Command::Category(category_change)) => {
+            category::change_category(&db, category_change, &config.filter, config.verbose)
+                .unwrap_or_else(|err| {
+                    println!("Could not update categories: {err}");
+                    process::exit(1);
+                });
+        }
+        Some(Command::Tag(operation)) => {
+            tag::change_tags(&db, operation, &config.filter, config.verbose).unwrap_or_else(
+                |err| {
+                    println!("Could not update tags: {err}");
+                    process::exit(1);
+                },
+            );
+        }
+        Some(Command::Rules(rule_set)) => {
+            rules::apply_rules(&db, rule_set, &config.filter, config.verbose).unwrap_or_else(
+                |err| {
+                    println!("Could not apply rules: {err}");
+                    process::exit(1);
+                },
+            );
+        }
+        Some(Command::Limits(torrent_limits)) => {
+            limits::change_limits(&db, torrent_limits, &config.filter, config.verbose)
+                .unwrap_or_else(|err| {
+                    println!("Could not update limits: {err}");
+                    process::exit(1);
+                });
+        }
+        Some(Command::Queue(operation)) => {
+            queue::reorder_queue(&db, operation, &config.filter, config.verbose).unwrap_or_else(
+                |err| {
+                    println!("Could not reorder queue: {err}");
+                    process::exit(1);
+                },
+            );
+        }
+        Some(Command::State(change)) => {
+            state::change_state(&db, change, &config.filter, config.verbose).unwrap_or_else(
+                |err| {
+                    println!("Could not update state: {err}");
+                    process::exit(1);
+                },
+            );
+        }
+        Some(Command::Rename(request)) => {
+            rename::rename_torrent(&db, request, &config.filter, config.verbose).unwrap_or_else(
+                |err| {
+                    println!("Could not rename torrent: {err}");
+                    process::exit(1);
+                },
+            );
+        }
+        Some(Command::Trackers(edit)) => {
+            trackers::edit_trackers(&db, edit, &config.filter, config.verbose).unwrap_or_else(
+                |err| {
+                    println!("Could not edit trackers: {err}");
+                    process::exit(1);
+                },
+            );
+        }
+        Some(Command::Stats) => {
+            stats::show_stats(&db, &config.filter, config.verbose).unwrap_or_else(|err| {
+                println!("Could not summarise torrents: {err}");
+                process::exit(1);
+            });
+        }
+        Some(Command::StatsAdjust(adjustment)) => {
+            counters::adjust_stats(&db, adjustment, &config.filter, config.verbose).unwrap_or_else(
+                |err| {
+                    println!("Could not update transfer statistics: {err}");
+                    process::exit(1);
+                },
+            );
+        }
+        Some(Command::TrackerReport) => {
+            tracker_report::report_trackers(&db, &config.filter, config.verbose).unwrap_or_else(
+                |err| {
+                    println!("Could not report trackers: {err}");
+                    process::exit(1);
+                },
+            );
+        }
+        Some(Command::TrackerPolicy(run)) => {
+            tracker_policy::enforce_tracker_policy(&db, run, &config.filter, config.verbose)
+                .unwrap_or_else(|err| {
+                    println!("Could not enforce tracker policy: {err}");
+                    process::exit(1);
+                });
+        }
+        Some(Command::Passkey(change)) => {
+            passkey::change_passkey(&db, change, &config.filter, config.verbose).unwrap_or_else(
+                |err| {
+                    println!("Could not replace passkey: {err}");
+                    process::exit(1);
+                },
+            );
+        }
+        Some(Command::Webseeds(edit)) => {
+            webseeds::edit_webseeds(&db, edit, &config.filter, config.verbose).unwrap_or_else(
+                |err| {
+                    println!("Could not edit web seeds: {err}");
+                    process::exit(1);
+                },
+            );
+        }
+        Some(Command::Import(run)) => {
+            import::import_torrents(&db, run, config.verbose).unwrap_or_else(|err| {
+                println!("Could not import torrents: {err}");
+                process::exit(1);
+            });
+        }
+        Some(Command::Export(run)) => {
+            export::export_torrents(&db, run, &config.filter, config.verbose).unwrap_or_else(
+                |err| {
+                    println!("Could not export torrents: {err}");
+                    process::exit(1);
+                },
+            );
+        }
+        Some(Command::Merge(run)) => {
+            merge::merge_databases(&db, run, &config.filter, config.verbose).unwrap_or_else(
+                |err| {
+                    println!("Could not merge databases: {err}");
+                    process::exit(1);
+                },
+            );
+        }
+        Some(Command::Diff(run)) => {
+            diff::diff_databases(Some(&db), run, &config.filter, config.verbose).unwrap_or_else(
+                |err| {
+                    println!("Could not compare databases: {err}");
+                    process::exit(1);
+                },
+            );
+        }
+        None => {}
+    }
+
     if let Some(save_path) = config.save_path {
         save_path::change_save_path(&db, save_path, config.verbose).unwrap_or_else(|err| {
             println!("Could not update save paths: {err}");
@@ -29,6 +220,13 @@ fn main() -> Result<(), Box<dyn Error>> {
         });
     }
 
+    if let Some(download_path) = config.download_path {
+        save_path::change_download_path(&db, download_path, config.verbose).unwrap_or_else(|err| {
+            println!("Could not update download paths: {err}");
+            process::exit(1);
+        });
+    }
+
     if let Some(tracker_url) = config.tracker_url {
         tracker_url::change_tracker_url(&db, tracker_url, config.verbose).unwrap_or_else(|err| {
             println!("Could not update tracker URLs: {err}");
@@ -0,0 +1,468 @@
+//! qbfrt path module
+//!
+//! qB stores a torrent's save path in two representations. `target_save_path` always uses Unix-style
+//! separators, even on Windows, where network paths such as `\\server\share` are saved as
+//! `//server/share`. The fastresume `save_path` uses the separators of the OS qB runs on.
+//!
+//! [`QbPath`] understands Windows drive letters, UNC paths and POSIX paths, and renders each of them
+//! for both storage locations. Separators are only converted where they are separators, so a POSIX
+//! file name that legitimately contains a backslash is left alone. A leading `//` is only read as a
+//! UNC path by [`QbPath::parse_portable`], since elsewhere it is a valid POSIX path.
+//!
+//! ## Examples and Usage
+//! ```rs
+//! let path = QbPath::parse("\\\\nas\\share\\movies");
+//! assert_eq!(path.to_portable(), "//nas/share/movies");
+//! assert_eq!(path.to_native(Separator::Windows), "\\\\nas\\share\\movies");
+//! assert!(QbPath::parse_portable("//nas/share/movies").same_path(&path));
+//! ```
+
+use std::path::MAIN_SEPARATOR;
+
+/// Path separator style
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Separator {
+    /// Path slash '/' separators
+    Unix,
+    /// Windows backslash '\' separators
+    Windows,
+}
+
+impl Separator {
+    /// Returns the separator style of the current OS
+    pub fn os_default() -> Separator {
+        if MAIN_SEPARATOR == '\\' {
+            Separator::Windows
+        } else {
+            Separator::Unix
+        }
+    }
+
+    /// Returns the separator character
+    pub fn as_char(&self) -> char {
+        match self {
+            Separator::Unix => '/',
+            Separator::Windows => '\\',
+        }
+    }
+}
+
+/// The root a path starts from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PathRoot {
+    /// Relative path or path fragment
+    Relative,
+    /// Absolute path without a drive, e.g. `/home/user`
+    Root,
+    /// Windows drive letter, e.g. `D:\Downloads`
+    Drive(char),
+    /// Windows UNC share, e.g. `\\server\share`
+    Unc {
+        /// Server name
+        server: String,
+        /// Share name
+        share: String,
+    },
+}
+
+/// A save path that can be rendered for either qB storage location
+#[derive(Debug, Clone)]
+pub struct QbPath {
+    /// The root the path starts from
+    pub root: PathRoot,
+    /// Path components after the root, without separators
+    pub components: Vec<String>,
+    /// Separator style the path was parsed with
+    pub separator: Separator,
+}
+
+/// Returns true if a path or path fragment uses Windows-style separators
+///
+/// Paths starting with a drive letter or a UNC prefix are Windows paths. Otherwise a path is only
+/// treated as Windows-style if it contains backslashes but no slashes, since a backslash is a valid
+/// character in POSIX file names. A leading `//` is a valid POSIX path, see
+/// [`QbPath::parse_portable`] for qB's `//server/share` form.
+pub fn is_windows_style(path: &str) -> bool {
+    has_drive_prefix(path)
+        || path.starts_with("\\\\")
+        || (path.contains('\\') && !path.contains('/'))
+}
+
+/// Converts a path or path fragment to the Unix-style separators used by `target_save_path`
+///
+/// Unlike [`QbPath`], leading and trailing separators are kept, so the result can be used for
+/// partial path replacement.
+pub fn fragment_to_portable(fragment: &str) -> String {
+    if is_windows_style(fragment) {
+        fragment.replace('\\', "/")
+    } else {
+        fragment.to_string()
+    }
+}
+
+/// Returns the current path of every file relative to the save path, using '/' separators
+///
+/// libtorrent only writes `mapped_files` up to the last renamed file and leaves the entries of
+/// files that were not renamed empty, so files without an entry keep their original path. Mapped
+/// paths use the separators of the OS qB runs on, `windows` converts backslash separators.
+pub fn mapped_file_paths(
+    mapped_files: Option<&[String]>,
+    original_paths: &[String],
+    windows: bool,
+) -> Vec<String> {
+    original_paths
+        .iter()
+        .enumerate()
+        .map(|(index, original)| {
+            match mapped_files
+                .and_then(|mapped_files| mapped_files.get(index))
+                .filter(|mapped| !mapped.is_empty())
+            {
+                Some(mapped) if windows => mapped.replace('\\', "/"),
+                Some(mapped) => mapped.clone(),
+                None => original.clone(),
+            }
+        })
+        .collect()
+}
+
+/// Returns true if the path starts with a drive letter such as `D:` or `D:\`
+fn has_drive_prefix(path: &str) -> bool {
+    let bytes = path.as_bytes();
+    bytes.len() >= 2
+        && bytes[0].is_ascii_alphabetic()
+        && bytes[1] == b':'
+        && (bytes.len() == 2 || bytes[2] == b'\\' || bytes[2] == b'/')
+}
+
+/// Splits a path on the given separators, dropping empty components
+fn split_components(path: &str, separators: &[char]) -> Vec<String> {
+    path.split(separators)
+        .filter(|component| !component.is_empty())
+        .map(String::from)
+        .collect()
+}
+
+impl QbPath {
+    /// Parses a path stored in the fastresume `save_path` or given on the command line
+    pub fn parse(path: &str) -> QbPath {
+        if is_windows_style(path) {
+            return QbPath::parse_windows(path);
+        }
+
+        QbPath {
+            root: if path.starts_with('/') {
+                PathRoot::Root
+            } else {
+                PathRoot::Relative
+            },
+            components: split_components(path, &['/']),
+            separator: Separator::Unix,
+        }
+    }
+
+    /// Parses a path stored in `target_save_path` or `download_path`
+    ///
+    /// qB saves Windows UNC paths as `//server/share` in these columns, so a path starting with
+    /// exactly two slashes is read as a UNC path.
+    pub fn parse_portable(path: &str) -> QbPath {
+        match path.starts_with("//") && !path.starts_with("///") {
+            true => QbPath::parse_windows(path),
+            false => QbPath::parse(path),
+        }
+    }
+
+    /// Parses a path known to be a Windows path, treating both '\' and '/' as separators
+    ///
+    /// This is useful when a partial replacement leaves a Windows path with mixed separators.
+    pub fn parse_windows(path: &str) -> QbPath {
+        let separator = if path.contains('\\') {
+            Separator::Windows
+        } else {
+            Separator::Unix
+        };
+
+        if has_drive_prefix(path) {
+            return QbPath {
+                root: PathRoot::Drive(path.chars().next().unwrap()),
+                components: split_components(&path[2..], &['\\', '/']),
+                separator,
+            };
+        }
+
+        let mut components = split_components(path, &['\\', '/']);
+
+        // qB saves UNC paths as //server/share in target_save_path
+        if (path.starts_with("\\\\") || path.starts_with("//")) && components.len() >= 2 {
+            let server = components.remove(0);
+            let share = components.remove(0);
+            return QbPath {
+                root: PathRoot::Unc { server, share },
+                components,
+                separator,
+            };
+        }
+
+        QbPath {
+            root: if path.starts_with(['\\', '/']) {
+                PathRoot::Root
+            } else {
+                PathRoot::Relative
+            },
+            components,
+            separator,
+        }
+    }
+
+    /// Returns true if the path is a Windows drive or UNC path
+    pub fn is_windows(&self) -> bool {
+        matches!(self.root, PathRoot::Drive(_) | PathRoot::Unc { .. })
+    }
+
+    /// Returns true if both paths point to the same location, regardless of separator style
+    ///
+    /// Windows paths are compared case-insensitively, as Windows file systems are.
+    pub fn same_path(&self, other: &QbPath) -> bool {
+        self.components.len() == other.components.len() && self.starts_with(other)
+    }
+
+    /// Returns true if the path is `prefix` or lies below it
+    ///
+    /// Windows paths are compared case-insensitively, as Windows file systems are.
+    pub fn starts_with(&self, prefix: &QbPath) -> bool {
+        self.same_root(prefix)
+            && self.components.len() >= prefix.components.len()
+            && self
+                .components
+                .iter()
+                .zip(&prefix.components)
+                .all(|(a, b)| {
+                    if self.is_windows() {
+                        a.eq_ignore_ascii_case(b)
+                    } else {
+                        a == b
+                    }
+                })
+    }
+
+    /// Replaces the leading `prefix` of the path with `replacement`
+    ///
+    /// Returns `None` if the path does not start with `prefix`.
+    pub fn replace_prefix(&self, prefix: &QbPath, replacement: &QbPath) -> Option<QbPath> {
+        if !self.starts_with(prefix) {
+            return None;
+        }
+
+        let mut components = replacement.components.clone();
+        components.extend_from_slice(&self.components[prefix.components.len()..]);
+
+        Some(QbPath {
+            root: replacement.root.clone(),
+            components,
+            separator: replacement.separator,
+        })
+    }
+
+    /// Returns the root of the path without any components, e.g. `D:\` or `\\server\share`
+    pub fn root_path(&self) -> QbPath {
+        QbPath {
+            root: self.root.clone(),
+            components: Vec::new(),
+            separator: self.separator,
+        }
+    }
+
+    /// Compares the roots of two paths, ignoring the case of drive letters
+    fn same_root(&self, other: &QbPath) -> bool {
+        match (&self.root, &other.root) {
+            (PathRoot::Drive(a), PathRoot::Drive(b)) => a.eq_ignore_ascii_case(b),
+            (
+                PathRoot::Unc { server, share },
+                PathRoot::Unc {
+                    server: s,
+                    share: sh,
+                },
+            ) => server.eq_ignore_ascii_case(s) && share.eq_ignore_ascii_case(sh),
+            (a, b) => a == b,
+        }
+    }
+
+    /// Renders the path the way qB stores it in `target_save_path`, with Unix-style separators
+    pub fn to_portable(&self) -> String {
+        self.render(Separator::Unix)
+    }
+
+    /// Renders the path the way qB stores it in the fastresume `save_path`, using the given separator
+    pub fn to_native(&self, separator: Separator) -> String {
+        self.render(separator)
+    }
+
+    /// Joins the root and components with the given separator
+    fn render(&self, separator: Separator) -> String {
+        let sep = separator.as_char().to_string();
+        let rest = self.components.join(&sep);
+
+        match &self.root {
+            PathRoot::Relative => rest,
+            PathRoot::Root => format!("{sep}{rest}"),
+            PathRoot::Drive(drive) => format!("{drive}:{sep}{rest}"),
+            PathRoot::Unc { server, share } if rest.is_empty() => {
+                format!("{sep}{sep}{server}{sep}{share}")
+            }
+            PathRoot::Unc { server, share } => format!("{sep}{sep}{server}{sep}{share}{sep}{rest}"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn classifies_windows_style_paths() {
+        let cases = [
+            ("D:\\Downloads", true),
+            ("D:/Downloads", true),
+            ("D:", true),
+            ("\\\\nas\\share", true),
+            ("Downloads\\Movies", true),
+            ("/home/user", false),
+            ("//home/user", false),
+            ("/home/user/back\\slash", false),
+            ("relative/path", false),
+            ("De:/not-a-drive", false),
+        ];
+        for (path, windows) in cases {
+            assert_eq!(is_windows_style(path), windows, "{path}");
+        }
+    }
+
+    #[test]
+    fn parses_roots() {
+        let cases = [
+            (
+                "D:\\Downloads\\Movies",
+                PathRoot::Drive('D'),
+                vec!["Downloads", "Movies"],
+            ),
+            ("d:/Downloads/", PathRoot::Drive('d'), vec!["Downloads"]),
+            (
+                "\\\\nas\\share\\movies",
+                PathRoot::Unc {
+                    server: String::from("nas"),
+                    share: String::from("share"),
+                },
+                vec!["movies"],
+            ),
+            ("/home//user/", PathRoot::Root, vec!["home", "user"]),
+            ("//home/user", PathRoot::Root, vec!["home", "user"]),
+            (
+                "/srv/back\\slash",
+                PathRoot::Root,
+                vec!["srv", "back\\slash"],
+            ),
+            (
+                "D:\\Downloads/Movies",
+                PathRoot::Drive('D'),
+                vec!["Downloads", "Movies"],
+            ),
+            (
+                "relative/path",
+                PathRoot::Relative,
+                vec!["relative", "path"],
+            ),
+        ];
+        for (path, root, components) in cases {
+            let parsed = QbPath::parse(path);
+            assert_eq!(parsed.root, root, "{path}");
+            assert_eq!(parsed.components, components, "{path}");
+        }
+    }
+
+    #[test]
+    fn parses_portable_unc_paths() {
+        let unc = QbPath::parse_portable("//nas/share/movies");
+        assert!(unc.is_windows());
+        assert!(unc.same_path(&QbPath::parse("\\\\nas\\share\\movies")));
+        assert_eq!(unc.to_native(Separator::Windows), "\\\\nas\\share\\movies");
+
+        let posix = QbPath::parse_portable("///srv/torrents");
+        assert_eq!(posix.root, PathRoot::Root);
+        assert_eq!(
+            QbPath::parse_portable("/srv/torrents").to_portable(),
+            "/srv/torrents"
+        );
+    }
+
+    #[test]
+    fn renders_both_representations() {
+        let cases = [
+            (
+                "D:\\Downloads\\Movies",
+                "D:/Downloads/Movies",
+                "D:\\Downloads\\Movies",
+            ),
+            ("\\\\nas\\share", "//nas/share", "\\\\nas\\share"),
+            ("/home/user", "/home/user", "\\home\\user"),
+        ];
+        for (path, portable, windows) in cases {
+            let parsed = QbPath::parse(path);
+            assert_eq!(parsed.to_portable(), portable, "{path}");
+            assert_eq!(parsed.to_native(Separator::Windows), windows, "{path}");
+        }
+    }
+
+    #[test]
+    fn compares_windows_paths_case_insensitively() {
+        let cases = [
+            ("D:\\Foo", "d:\\foo", true),
+            ("D:\\Foo", "D:/Foo/", true),
+            ("\\\\NAS\\Share\\Foo", "\\\\nas\\share\\foo", true),
+            ("/home/Foo", "/home/foo", false),
+            ("/home/foo", "/home/foo/", true),
+            ("D:\\Foo", "D:\\Foo\\Bar", false),
+            ("D:\\Foo", "E:\\Foo", false),
+        ];
+        for (a, b, same) in cases {
+            let (a, b) = (QbPath::parse(a), QbPath::parse(b));
+            assert_eq!(a.same_path(&b), same, "{a:?} {b:?}");
+            assert_eq!(b.same_path(&a), same, "{a:?} {b:?}");
+            if same {
+                assert!(a.starts_with(&b) && b.starts_with(&a), "{a:?} {b:?}");
+            }
+        }
+    }
+
+    #[test]
+    fn replaces_prefixes() {
+        let path = QbPath::parse("D:\\Torrents\\Movies");
+        let replaced = path
+            .replace_prefix(
+                &QbPath::parse("d:\\torrents"),
+                &QbPath::parse("/mnt/torrents"),
+            )
+            .unwrap();
+        assert_eq!(replaced.to_portable(), "/mnt/torrents/Movies");
+        assert!(path
+            .replace_prefix(&QbPath::parse("D:\\Other"), &QbPath::parse("/mnt"))
+            .is_none());
+        // A prefix only matches whole components
+        assert!(!QbPath::parse("/srv/torrents2").starts_with(&QbPath::parse("/srv/torrents")));
+    }
+
+    #[test]
+    fn keeps_unrenamed_mapped_files() {
+        let original = vec![String::from("Pack/a.bin"), String::from("Pack/b.bin")];
+        let mapped = vec![String::from("Renamed\\a.bin")];
+        assert_eq!(
+            mapped_file_paths(Some(&mapped), &original, true),
+            vec!["Renamed/a.bin", "Pack/b.bin"]
+        );
+        assert_eq!(mapped_file_paths(None, &original, false), original);
+        let posix = vec![String::new(), String::from("back\\slash.bin")];
+        assert_eq!(
+            mapped_file_paths(Some(&posix), &original, false),
+            vec!["Pack/a.bin", "back\\slash.bin"]
+        );
+    }
+}